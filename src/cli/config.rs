@@ -1,97 +1,1039 @@
+use ron::extensions::Extensions;
 use serde::Deserialize;
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::{BufReader, Read, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+use super::layout::Layout;
+use super::open::OpenerRule;
+use super::prompt::Picker;
+use crate::error::Error;
+use crate::tags::db::Mirror;
+
+/// the name of the library made of the options at the top level of the config
+pub const DEFAULT_LIBRARY: &str = "default";
 
-// TODO move this to ron, for consistency accross the code, and because ron can make this prettier
 #[derive(Clone)]
 pub struct Config {
+    /// the managed directory, database and link directory are the ones of this library
+    pub library: String,
     pub managed_directory: PathBuf,
+    /// `data.ron` in the managed directory by default
+    pub database_file: PathBuf,
     pub link_directory_name: PathBuf,
+    pub openers: Vec<OpenerRule>,
+    pub picker: Picker,
+    /// where files that are put in the managed directory go
+    pub layout: Layout,
+    /// where tags are written besides the database
+    pub mirror: Mirror,
+    /// every library, the default one first
+    pub libraries: Vec<Library>,
+    /// the library that is used without `--library`
+    pub default_library: String,
 }
 
-#[derive(Deserialize)]
+/// a separate set of files and tags, with its own managed directory, database and link directory
+#[derive(Clone)]
+pub struct Library {
+    pub name: String,
+    pub managed_directory: PathBuf,
+    pub database_file: PathBuf,
+    pub link_directory_name: PathBuf,
+}
+
+/// the config as it is written in the file, everything that is missing gets its default
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
 struct WeakConfig {
     managed_directory: Option<PathBuf>,
+    database_file: Option<PathBuf>,
     link_directory_name: Option<PathBuf>,
+    openers: Option<Vec<OpenerRule>>,
+    picker: Option<Picker>,
+    layout: Option<String>,
+    mirror_xattr: Option<bool>,
+    sidecars: Option<bool>,
+    libraries: Option<BTreeMap<String, WeakLibrary>>,
+    default_library: Option<String>,
 }
 
-fn config_path() -> PathBuf {
-    let mut config_dir = dirs::config_dir().unwrap();
-    config_dir.push("tagged.toml"); // maybe i could make this configurable, but it seems like a pain for something nobody wants
-    config_dir
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct WeakLibrary {
+    managed_directory: Option<PathBuf>,
+    database_file: Option<PathBuf>,
+    link_directory_name: Option<PathBuf>,
 }
 
-pub fn exists() -> bool {
-    config_path().exists()
+/// every key of the config, in the order of the default config
+pub const KEYS: [&str; 10] = [
+    "managed_directory",
+    "database_file",
+    "link_directory_name",
+    "picker",
+    "layout",
+    "mirror_xattr",
+    "sidecars",
+    "openers",
+    "libraries",
+    "default_library",
+];
+
+#[derive(Clone, Copy, PartialEq)]
+enum FileFormat {
+    Ron,
+    Toml,
 }
 
-pub fn read() -> Result<Config, String> {
-    if !exists() {
-        eprintln!("config file does not exist. Creating it now");
-        if let Err(e) = create_empty_config() {
-            return Err(e);
+/// one value of the effective config, see `config show`
+pub struct Setting {
+    pub key: &'static str,
+    pub value: String,
+    /// `default`, `config`, or the flag or environment variable that set it
+    pub source: &'static str,
+}
+
+/// the config file that is used, and the values from the command line and the environment that win over it
+pub struct Location {
+    pub path: PathBuf,
+    /// from `--library`
+    library: Option<String>,
+    /// from `--db`
+    database_file: Option<PathBuf>,
+    /// from `TAGGEDFILES_DIR`
+    managed_directory: Option<PathBuf>,
+}
+
+/// `path` comes from `--config` or `TAGGEDFILES_CONFIG`, otherwise the config directory is used
+pub fn locate(
+    path: Option<PathBuf>,
+    library: Option<String>,
+    database_file: Option<PathBuf>,
+) -> Result<Location, Error> {
+    let path = match path {
+        Some(path) => path,
+        None => default_path()?,
+    };
+    let managed_directory = std::env::var_os("TAGGEDFILES_DIR")
+        .filter(|x| !x.is_empty())
+        .map(PathBuf::from);
+    Ok(Location {
+        path,
+        library,
+        database_file,
+        managed_directory,
+    })
+}
+
+/// `tagged.ron`, or `tagged.toml` for configs made by older versions when there is no ron config
+fn default_path() -> Result<PathBuf, Error> {
+    let config_dir = match dirs::config_dir() {
+        Some(config_dir) => config_dir,
+        None => {
+            return Err(Error::Config(
+                "could not find the config directory".to_owned(),
+            ))
         }
+    };
+    let ron = config_dir.join("tagged.ron");
+    let toml = config_dir.join("tagged.toml");
+    if !ron.exists() && toml.exists() {
+        return Ok(toml);
     }
+    Ok(ron)
+}
 
-    let file_result = File::open(config_path());
-    if file_result.is_err() {
-        return Err("Couldnt open file".to_owned());
+fn file_format(path: &Path) -> FileFormat {
+    match path.extension() {
+        Some(extension) if extension == "toml" => FileFormat::Toml,
+        _ => FileFormat::Ron,
     }
-    let file = file_result.unwrap();
+}
+
+pub fn read(location: &Location) -> Result<Config, Error> {
+    if !location.path.exists() {
+        eprintln!("config file does not exist. Creating it now");
+        create_empty_config(&location.path)?;
+    }
+
+    resolve(load(&location.path)?, location)
+}
+
+fn read_text(path: &Path) -> Result<String, Error> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) => {
+            return Err(Error::Config(format!(
+                "Couldnt open {}, {}",
+                path.display(),
+                e
+            )))
+        }
+    };
 
     let mut output = String::new();
     let mut buf_reader = BufReader::new(file);
-    if buf_reader.read_to_string(&mut output).is_err() {
-        return Err("Couldnt read file".to_owned());
+    if let Err(e) = buf_reader.read_to_string(&mut output) {
+        return Err(Error::Config(format!(
+            "Couldnt read {}, {}",
+            path.display(),
+            e
+        )));
     }
+    Ok(output)
+}
 
-    let toml: WeakConfig = toml::from_str(&output).unwrap();
+fn load(path: &Path) -> Result<WeakConfig, Error> {
+    parse(&read_text(path)?, path)
+}
 
-    let mut tag_directory = PathBuf::new();
-    let mut link_directory_name = PathBuf::new();
+fn parse(text: &str, path: &Path) -> Result<WeakConfig, Error> {
+    match file_format(path) {
+        FileFormat::Ron => {
+            // so optional values can be written without `Some(...)`
+            let options = ron::Options::default().with_default_extension(Extensions::IMPLICIT_SOME);
+            match options.from_str(text) {
+                Ok(config) => Ok(config),
+                Err(e) => Err(Error::Config(format!(
+                    "invalid config {}, line {} column {}: {}",
+                    path.display(),
+                    e.position.line,
+                    e.position.col,
+                    e.code
+                ))),
+            }
+        }
+        // toml errors already show the line with a marker
+        FileFormat::Toml => match toml::from_str(text) {
+            Ok(config) => Ok(config),
+            Err(e) => Err(Error::Config(format!(
+                "invalid config {}, {}",
+                path.display(),
+                e
+            ))),
+        },
+    }
+}
+
+/// fills in the defaults, the command line and the environment win over the config file
+fn resolve(config: WeakConfig, location: &Location) -> Result<Config, Error> {
+    // the options at the top level are the default library
+    let mut weak_libraries = vec![(
+        DEFAULT_LIBRARY.to_owned(),
+        WeakLibrary {
+            managed_directory: config.managed_directory,
+            database_file: config.database_file,
+            link_directory_name: config.link_directory_name,
+        },
+    )];
+    for (name, library) in config.libraries.unwrap_or_default() {
+        check_library_name(&name)?;
+        weak_libraries.push((name, library));
+    }
+
+    let default_library = config.default_library.unwrap_or(DEFAULT_LIBRARY.to_owned());
+    let selected = location.library.clone().unwrap_or(default_library.clone());
+
+    let mut libraries = vec![];
+    for (name, mut library) in weak_libraries {
+        // the command line and the environment only change the library that is used
+        let mut database_file = None;
+        if name == selected {
+            if location.managed_directory.is_some() {
+                library.managed_directory = location.managed_directory.clone();
+            }
+            database_file = location.database_file.clone();
+        }
+        libraries.push(resolve_library(name, library, database_file)?);
+    }
+    let library = match libraries.iter().find(|x| x.name == selected) {
+        Some(library) => library.clone(),
+        None => {
+            return Err(Error::NotFound(format!(
+                "there is no library called '{}', see `libraries list`",
+                selected
+            )))
+        }
+    };
 
-    if let Some(result) = toml.managed_directory {
-        tag_directory.push(result);
-    } else {
+    let layout = match config.layout {
+        Some(template) => Layout::parse(&template)?,
+        None => Layout::default(),
+    };
+
+    Ok(Config {
+        library: library.name,
+        managed_directory: library.managed_directory,
+        database_file: library.database_file,
+        link_directory_name: library.link_directory_name,
+        openers: config.openers.unwrap_or_default(),
+        picker: config.picker.unwrap_or_default(),
+        layout,
+        mirror: Mirror {
+            xattr: config.mirror_xattr.unwrap_or(false),
+            sidecar: config.sidecars.unwrap_or(false),
+        },
+        libraries,
+        default_library,
+    })
+}
+
+/// `database_file` is from `--db`, relative paths from there are in the working directory instead of the managed directory
+fn resolve_library(
+    name: String,
+    library: WeakLibrary,
+    database_file: Option<PathBuf>,
+) -> Result<Library, Error> {
+    let managed_directory = match library.managed_directory {
+        Some(path) => expand_home(path),
         // TODO this could break windows, idk if home dir is even wanted behaviour in windows
-        tag_directory.push(dirs::home_dir().unwrap());
-        tag_directory.push("tagged".to_owned());
+        None => match dirs::home_dir() {
+            Some(home) if name == DEFAULT_LIBRARY => home.join("tagged"),
+            Some(home) => home.join(format!("tagged-{}", name)),
+            None => {
+                return Err(Error::Config(
+                    "could not find the home directory, set managed_directory in the config"
+                        .to_owned(),
+                ))
+            }
+        },
+    };
+
+    let database_file = match (database_file, library.database_file) {
+        (Some(path), _) => path,
+        (None, Some(path)) => managed_directory.join(expand_home(path)),
+        (None, None) => managed_directory.join("data.ron"),
+    };
+
+    Ok(Library {
+        name,
+        database_file,
+        link_directory_name: library
+            .link_directory_name
+            .unwrap_or(PathBuf::from("!link")),
+        managed_directory,
+    })
+}
+
+/// library names are used in the config without escaping, so only simple names are allowed
+pub fn check_library_name(name: &str) -> Result<(), Error> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|x| x.is_ascii_alphanumeric() || x == '-' || x == '_')
+    {
+        return Err(Error::Invalid(format!(
+            "invalid library name '{}', use letters, digits, - and _",
+            name
+        )));
+    }
+    if name == DEFAULT_LIBRARY {
+        return Err(Error::Invalid(format!(
+            "the library name '{}' is used for the options at the top of the config",
+            DEFAULT_LIBRARY
+        )));
+    }
+    Ok(())
+}
+
+/// `~/x` is `x` in the home directory
+fn expand_home(path: PathBuf) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path,
+    }
+}
+
+/// the effective value of every key
+pub fn settings(location: &Location) -> Result<Vec<Setting>, Error> {
+    let weak = match location.path.exists() {
+        true => load(&location.path)?,
+        false => WeakConfig::default(),
+    };
+    let source = |overridden: Option<&'static str>, set: bool| match (overridden, set) {
+        (Some(name), _) => name,
+        (None, true) => "config",
+        (None, false) => "default",
+    };
+    let mut sources = [
+        source(
+            location
+                .managed_directory
+                .as_ref()
+                .map(|_| "TAGGEDFILES_DIR"),
+            weak.managed_directory.is_some(),
+        ),
+        source(
+            location.database_file.as_ref().map(|_| "--db"),
+            weak.database_file.is_some(),
+        ),
+        source(None, weak.link_directory_name.is_some()),
+        source(None, weak.picker.is_some()),
+        source(None, weak.layout.is_some()),
+        source(None, weak.mirror_xattr.is_some()),
+        source(None, weak.sidecars.is_some()),
+        source(None, weak.openers.is_some()),
+        source(None, weak.libraries.is_some()),
+        source(None, weak.default_library.is_some()),
+    ];
+    let config = resolve(weak, location)?;
+    // a library from `libraries` has its own directories
+    if config.library != DEFAULT_LIBRARY {
+        for source in sources.iter_mut().take(3) {
+            if *source == "config" || *source == "default" {
+                *source = "libraries";
+            }
+        }
+    }
+
+    let openers = match ron::Options::default()
+        .with_default_extension(Extensions::IMPLICIT_SOME)
+        .to_string(&config.openers)
+    {
+        Ok(openers) => openers,
+        Err(e) => {
+            return Err(Error::Config(format!(
+                "could not serialize the openers, {}",
+                e
+            )))
+        }
+    };
+    let values = [
+        config.managed_directory.display().to_string(),
+        config.database_file.display().to_string(),
+        config.link_directory_name.display().to_string(),
+        config.picker.name().to_owned(),
+        config.layout.template().to_owned(),
+        config.mirror.xattr.to_string(),
+        config.mirror.sidecar.to_string(),
+        openers,
+        config
+            .libraries
+            .iter()
+            .map(|x| x.name.as_str())
+            .collect::<Vec<&str>>()
+            .join(", "),
+        config.default_library,
+    ];
+
+    Ok(KEYS
+        .iter()
+        .zip(values)
+        .zip(sources)
+        .map(|((key, value), source)| Setting { key, value, source })
+        .collect())
+}
+
+/// changes one value in the config file, the rest of the file and its comments stay as they are
+pub fn set(location: &Location, key: &str, value: &str) -> Result<(), Error> {
+    let path = &location.path;
+    if !path.exists() {
+        create_empty_config(path)?;
+    }
+    let format = file_format(path);
+
+    let literal = match key {
+        "managed_directory"
+        | "database_file"
+        | "link_directory_name"
+        | "layout"
+        | "default_library" => {
+            if key == "layout" {
+                Layout::parse(value)?;
+            }
+            match format {
+                FileFormat::Ron => ron::to_string(value).unwrap_or_default(),
+                FileFormat::Toml => toml::Value::String(value.to_owned()).to_string(),
+            }
+        }
+        "picker" => {
+            let picker = match Picker::ALL.iter().find(|x| x.name() == value) {
+                Some(picker) => picker,
+                None => {
+                    return Err(Error::Invalid(format!(
+                        "unknown picker '{}', use one of {}",
+                        value,
+                        Picker::ALL.map(|x| x.name()).join(", ")
+                    )))
+                }
+            };
+            match format {
+                FileFormat::Ron => picker.name().to_owned(),
+                FileFormat::Toml => format!("\"{}\"", picker.name()),
+            }
+        }
+        "mirror_xattr" | "sidecars" => match value {
+            "true" | "false" => value.to_owned(),
+            _ => {
+                return Err(Error::Invalid(format!(
+                    "{} has to be true or false, not '{}'",
+                    key, value
+                )))
+            }
+        },
+        "openers" => {
+            return Err(Error::Invalid(
+                "openers can not be set from the command line, use `config edit`".to_owned(),
+            ))
+        }
+        "libraries" => {
+            return Err(Error::Invalid(
+                "use `libraries create` and `libraries remove` to change the libraries".to_owned(),
+            ))
+        }
+        _ => return Err(unknown_key(key)),
+    };
+
+    let text = set_line(&read_text(path)?, key, &literal, format);
+    // nothing is written when the result would not be valid
+    if let Err(e) = parse(&text, path).and_then(|x| resolve(x, location)) {
+        return Err(Error::Config(format!(
+            "could not set {}, the config would be invalid, use `config edit` instead. {}",
+            key, e
+        )));
+    }
+    if let Err(e) = std::fs::write(path, text) {
+        return Err(Error::Config(format!(
+            "couldnt write to {}, {}",
+            path.display(),
+            e
+        )));
+    }
+    Ok(())
+}
+
+/// checks the config after it was edited by hand
+pub fn validate(location: &Location) -> Result<(), Error> {
+    resolve(load(&location.path)?, location)?;
+    Ok(())
+}
+
+pub fn unknown_key(key: &str) -> Error {
+    Error::Invalid(format!(
+        "unknown config key '{}', the keys are {}",
+        key,
+        KEYS.join(", ")
+    ))
+}
+
+/// adds a library to the config file, the rest of the file stays as it is
+pub fn add_library(location: &Location, name: &str, directory: &Path) -> Result<(), Error> {
+    check_library_name(name)?;
+    let path = &location.path;
+    if !path.exists() {
+        create_empty_config(path)?;
+    }
+    let weak = load(path)?;
+    if weak
+        .libraries
+        .as_ref()
+        .is_some_and(|x| x.contains_key(name))
+    {
+        return Err(Error::Invalid(format!(
+            "there already is a library called '{}'",
+            name
+        )));
+    }
+
+    let directory = directory.to_string_lossy();
+    let mut lines: Vec<String> = read_text(path)?.lines().map(|x| x.to_owned()).collect();
+    match file_format(path) {
+        FileFormat::Ron => {
+            let entry = format!(
+                "        \"{}\": (managed_directory: {}),",
+                name,
+                ron::to_string(&directory).unwrap_or_default()
+            );
+            match lines
+                .iter()
+                .position(|x| x.trim_start().starts_with("libraries:"))
+            {
+                Some(i) => lines.insert(i + 1, entry),
+                None => {
+                    // above the commented out example, or at the start
+                    let i = match lines
+                        .iter()
+                        .position(|x| x.trim_start().starts_with("// libraries:"))
+                    {
+                        Some(i) => i,
+                        None => lines
+                            .iter()
+                            .position(|x| x.trim_start().starts_with('('))
+                            .map_or(0, |x| x + 1),
+                    };
+                    lines.splice(
+                        i..i,
+                        ["    libraries: {".to_owned(), entry, "    },".to_owned()],
+                    );
+                }
+            }
+        }
+        // tables can go at the end
+        FileFormat::Toml => {
+            lines.push(String::new());
+            lines.push(format!("[libraries.{}]", name));
+            lines.push(format!(
+                "managed_directory = {}",
+                toml::Value::String(directory.into_owned())
+            ));
+        }
+    }
+
+    write_checked(location, lines, |weak| {
+        weak.libraries
+            .as_ref()
+            .is_some_and(|x| x.contains_key(name))
+    })
+}
+
+/// removes a library from the config file, its files and database stay where they are
+pub fn remove_library(location: &Location, name: &str) -> Result<(), Error> {
+    let path = &location.path;
+    let weak = match path.exists() {
+        true => load(path)?,
+        false => WeakConfig::default(),
+    };
+    if !weak
+        .libraries
+        .as_ref()
+        .is_some_and(|x| x.contains_key(name))
+    {
+        return Err(Error::NotFound(format!(
+            "there is no library called '{}' in the config",
+            name
+        )));
+    }
+    if weak.default_library.as_deref() == Some(name) {
+        return Err(Error::Invalid(format!(
+            "'{}' is the default library, change default_library first",
+            name
+        )));
     }
 
-    if let Some(result) = toml.link_directory_name {
-        link_directory_name.push(result);
-    } else {
-        link_directory_name.push("!link".to_owned());
+    let mut lines: Vec<String> = read_text(path)?.lines().map(|x| x.to_owned()).collect();
+    match file_format(path) {
+        FileFormat::Ron => {
+            let start = lines
+                .iter()
+                .position(|x| x.trim_start().starts_with("libraries:"))
+                .unwrap_or(0);
+            let prefix = format!("\"{}\":", name);
+            if let Some(i) = lines
+                .iter()
+                .skip(start)
+                .position(|x| x.trim_start().starts_with(&prefix))
+            {
+                lines.remove(start + i);
+            }
+        }
+        FileFormat::Toml => {
+            let headers = [
+                format!("[libraries.{}]", name),
+                format!("[libraries.\"{}\"]", name),
+            ];
+            if let Some(start) = lines
+                .iter()
+                .position(|x| headers.contains(&x.trim().to_owned()))
+            {
+                let end = lines
+                    .iter()
+                    .skip(start + 1)
+                    .position(|x| x.trim_start().starts_with('['))
+                    .map_or(lines.len(), |x| start + 1 + x);
+                lines.drain(start..end);
+            }
+        }
     }
 
-    return Ok(Config {
-        managed_directory: tag_directory,
-        link_directory_name,
+    write_checked(location, lines, |weak| {
+        !weak
+            .libraries
+            .as_ref()
+            .is_some_and(|x| x.contains_key(name))
+    })
+}
+
+/// writes the lines if they are a valid config where `done` is true, otherwise nothing is written
+fn write_checked(
+    location: &Location,
+    lines: Vec<String>,
+    done: impl Fn(&WeakConfig) -> bool,
+) -> Result<(), Error> {
+    let path = &location.path;
+    let mut text = lines.join("\n");
+    text.push('\n');
+
+    let checked = parse(&text, path).and_then(|weak| match done(&weak) {
+        true => resolve(weak, location).map(|_| ()),
+        false => Err(Error::Config("the change did not work".to_owned())),
     });
+    if let Err(e) = checked {
+        return Err(Error::Config(format!(
+            "could not change {} line by line, use `config edit` instead. {}",
+            path.display(),
+            e
+        )));
+    }
+
+    if let Err(e) = std::fs::write(path, text) {
+        return Err(Error::Config(format!(
+            "couldnt write to {}, {}",
+            path.display(),
+            e
+        )));
+    }
+    Ok(())
 }
 
-pub fn create_empty_config() -> Result<(), String> {
-    let config_path = config_path();
-    if exists() {
-        return Err("config already exists".to_owned());
+/// replaces the line that sets the key, or else the commented out default, or adds a line
+fn set_line(text: &str, key: &str, literal: &str, format: FileFormat) -> String {
+    let (separator, comment, assignment) = match format {
+        FileFormat::Ron => (':', "//", format!("{}: {},", key, literal)),
+        FileFormat::Toml => ('=', "#", format!("{} = {}", key, literal)),
+    };
+    let sets_key = |line: &str| {
+        line.trim_start()
+            .strip_prefix(key)
+            .is_some_and(|rest| rest.trim_start().starts_with(separator))
+    };
+    let commented = |line: &str| {
+        line.trim_start()
+            .strip_prefix(comment)
+            .is_some_and(&sets_key)
+    };
+
+    let mut lines: Vec<String> = text.lines().map(|x| x.to_owned()).collect();
+    // libraries have the same keys, only the ones at the top are meant
+    let top_level: Vec<bool> = match format {
+        FileFormat::Toml => {
+            let first_table = lines
+                .iter()
+                .position(|x| x.trim_start().starts_with('['))
+                .unwrap_or(lines.len());
+            (0..lines.len()).map(|i| i < first_table).collect()
+        }
+        FileFormat::Ron => ron_depths(&lines).into_iter().map(|x| x == 1).collect(),
+    };
+    let find = |matches: &dyn Fn(&str) -> bool| {
+        (0..lines.len()).find(|&i| top_level[i] && matches(&lines[i]))
+    };
+    match find(&sets_key).or(find(&commented)) {
+        Some(i) => {
+            let indentation: String = lines[i].chars().take_while(|x| x.is_whitespace()).collect();
+            lines[i] = format!("{}{}", indentation, assignment);
+        }
+        None => match format {
+            // top level keys have to come before the first table
+            FileFormat::Toml => {
+                let i = lines
+                    .iter()
+                    .position(|x| x.trim_start().starts_with('['))
+                    .unwrap_or(lines.len());
+                lines.insert(i, assignment);
+            }
+            FileFormat::Ron => {
+                let i = lines
+                    .iter()
+                    .position(|x| x.trim_start().starts_with('('))
+                    .map_or(0, |x| x + 1);
+                lines.insert(i, format!("    {}", assignment));
+            }
+        },
     }
 
-    // TODO the current way of doing defaults is basically hidden to the normal user,
-    // fix this by appending the defaults for unset values to the config
+    let mut text = lines.join("\n");
+    text.push('\n');
+    text
+}
 
-    // managed_directory: Option<PathBuf>,
-    // link_directory_name: Option<PathBuf>,
-    let default_config =
-        "# welcome to the config file :), here are the default values\n# managed_directory = \"~/tagged\"\n# link_directory_name = \"!link\"".as_bytes();
+/// how deep in brackets each line starts, the keys at the top of a ron config are at 1
+///
+/// brackets in strings and comments are not counted
+fn ron_depths(lines: &[String]) -> Vec<usize> {
+    let mut depths = vec![];
+    let mut depth: usize = 0;
+    for line in lines {
+        depths.push(depth);
+        let mut in_string = false;
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' if in_string => {
+                    chars.next();
+                }
+                '"' => in_string = !in_string,
+                '/' if !in_string && chars.peek() == Some(&'/') => break,
+                '(' | '[' | '{' if !in_string => depth += 1,
+                ')' | ']' | '}' if !in_string => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+    }
+    depths
+}
 
+pub fn create_empty_config(config_path: &Path) -> Result<(), Error> {
+    if config_path.exists() {
+        return Err(Error::Config("config already exists".to_owned()));
+    }
+
+    let default_config = match file_format(config_path) {
+        FileFormat::Ron => DEFAULT_RON,
+        FileFormat::Toml => DEFAULT_TOML,
+    }
+    .as_bytes();
+
+    if let Some(parent) = config_path.parent() {
+        if let Err(e) = std::fs::create_dir_all(parent) {
+            return Err(Error::Config(format!(
+                "could not create config directory, {}",
+                e
+            )));
+        }
+    }
     let file = File::create(config_path);
     if let Ok(mut new_file) = file {
-        if new_file.write_all(default_config).is_err() {
-            return Err("couldnt write to config file".to_owned());
+        if let Err(e) = new_file.write_all(default_config) {
+            return Err(Error::Config(format!(
+                "couldnt write to config file, {}",
+                e
+            )));
         }
-    } else if file.is_err() {
-        return Err("could not create config file".to_owned());
+    } else if let Err(e) = file {
+        return Err(Error::Config(format!(
+            "could not create config file, {}",
+            e
+        )));
     };
-    return Ok(());
+    Ok(())
+}
+
+const DEFAULT_RON: &str = r#"// the config of TaggedFiles, every option is listed with its default value
+// remove the `//` in front of an option to change it, or use `TaggedFiles config set <key> <value>`
+(
+    // where the database is, and where files are moved, copied or linked to
+    // managed_directory: "~/tagged",
+
+    // the database, relative paths are in the managed directory
+    // database_file: "data.ron",
+
+    // the directory inside the managed directory that getaslinkdirectory fills with symlinks
+    // link_directory_name: "!link",
+
+    // the fuzzy finder to choose between files: builtin, fzf or skim
+    // picker: builtin,
+
+    // where files go inside the managed directory
+    // placeholders: {name} {stem} {extension} {primary_tag} {year} {month}
+    // e.g. "{primary_tag}/{year}/{name}"
+    // layout: "{name}",
+
+    // also write the tags of files to the user.xdg.tags extended attribute, which file managers like dolphin read
+    // `TaggedFiles sync-xattr` reads tags from there
+    // mirror_xattr: false,
+
+    // also write the tags of the files in a directory to a .tagged.ron in that directory
+    // so a copied directory brings its tags along, `TaggedFiles check` reads them
+    // sidecars: false,
+
+    // other libraries, each with its own managed directory, database and link directory
+    // they have the same options as the top of this file, which is the library called "default"
+    // commands use another library with --library <name>, `TaggedFiles libraries create <name>` adds one
+    // libraries: {
+    //     "work": (managed_directory: "~/work-files"),
+    // },
+
+    // the library that is used without --library
+    // default_library: "default",
+
+    // rules for `open` and `edit`, the first matching rule is used
+    // without a matching rule `open` uses xdg-open and `edit` uses $EDITOR
+    // a rule can have action (open or edit), mime, extension, tag, kind (file or directory) and detach
+    // openers: [
+    //     (command: "nautilus {path}", kind: directory, detach: true),
+    //     (command: "mpv", mime: "video/*"),
+    //     (command: "hx", action: edit, tag: "code"),
+    //     (command: "zathura", extension: ["pdf", "epub"]),
+    // ],
+)
+"#;
+
+const DEFAULT_TOML: &str = r#"# the config of TaggedFiles, every option is listed with its default value
+# remove the `#` in front of an option to change it, or use `TaggedFiles config set <key> <value>`
+
+# where the database is, and where files are moved, copied or linked to
+# managed_directory = "~/tagged"
+
+# the database, relative paths are in the managed directory
+# database_file = "data.ron"
+
+# the directory inside the managed directory that getaslinkdirectory fills with symlinks
+# link_directory_name = "!link"
+
+# the fuzzy finder to choose between files: builtin, fzf or skim
+# picker = "builtin"
+
+# where files go inside the managed directory
+# placeholders: {name} {stem} {extension} {primary_tag} {year} {month}
+# e.g. "{primary_tag}/{year}/{name}"
+# layout = "{name}"
+
+# also write the tags of files to the user.xdg.tags extended attribute, which file managers like dolphin read
+# `TaggedFiles sync-xattr` reads tags from there
+# mirror_xattr = false
+
+# also write the tags of the files in a directory to a .tagged.ron in that directory
+# so a copied directory brings its tags along, `TaggedFiles check` reads them
+# sidecars = false
+
+# the library that is used without --library
+# default_library = "default"
+
+# other libraries, each with its own managed directory, database and link directory
+# they have the same options as the top of this file, which is the library called "default"
+# commands use another library with --library <name>, `TaggedFiles libraries create <name>` adds one
+# [libraries.work]
+# managed_directory = "~/work-files"
+
+# rules for `open` and `edit`, the first matching rule is used
+# without a matching rule `open` uses xdg-open and `edit` uses $EDITOR
+# a rule can have action (open or edit), mime, extension, tag, kind (file or directory) and detach
+# [[openers]]
+# command = "nautilus {path}"
+# kind = "directory"
+# detach = true
+#
+# [[openers]]
+# command = "zathura"
+# extension = ["pdf", "epub"]
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a location for a config in its own directory under the temp dir, anything left from an earlier run is removed first
+    fn location(test: &str, file_name: &str) -> Location {
+        let directory =
+            std::env::temp_dir().join(format!("tf-config-{}-{}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        Location {
+            path: directory.join(file_name),
+            library: None,
+            database_file: None,
+            managed_directory: None,
+        }
+    }
+
+    fn library_names(location: &Location) -> Vec<String> {
+        let mut names: Vec<String> = load(&location.path)
+            .unwrap()
+            .libraries
+            .unwrap_or_default()
+            .into_keys()
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn set_line_uncomments_the_default() {
+        for (text, format, literal, expected) in [
+            (DEFAULT_RON, FileFormat::Ron, "fzf", "    picker: fzf,"),
+            (
+                DEFAULT_TOML,
+                FileFormat::Toml,
+                "\"fzf\"",
+                "picker = \"fzf\"",
+            ),
+        ] {
+            let changed = set_line(text, "picker", literal, format);
+            assert!(changed.lines().any(|x| x == expected), "{}", changed);
+            assert_eq!(changed.lines().count(), text.lines().count());
+        }
+    }
+
+    #[test]
+    fn set_line_replaces_the_value() {
+        let text = "(\n    picker: fzf,\n    // picker: builtin,\n)\n";
+        assert_eq!(
+            set_line(text, "picker", "skim", FileFormat::Ron),
+            "(\n    picker: skim,\n    // picker: builtin,\n)\n"
+        );
+    }
+
+    #[test]
+    fn set_line_adds_a_missing_key() {
+        assert_eq!(
+            set_line("(\n)\n", "sidecars", "true", FileFormat::Ron),
+            "(\n    sidecars: true,\n)\n"
+        );
+        assert_eq!(
+            set_line(
+                "[libraries.work]\nmanaged_directory = \"/w\"\n",
+                "sidecars",
+                "true",
+                FileFormat::Toml
+            ),
+            "sidecars = true\n[libraries.work]\nmanaged_directory = \"/w\"\n"
+        );
+    }
+
+    #[test]
+    fn set_line_leaves_libraries_alone() {
+        let ron = "(\n    libraries: {\n        \"work\": (managed_directory: \"/w\"),\n        \"old\": (\n            managed_directory: \"/o\",\n        ),\n    },\n)\n";
+        let changed = set_line(ron, "managed_directory", "\"/m\"", FileFormat::Ron);
+        assert!(changed.contains("\"work\": (managed_directory: \"/w\"),"));
+        assert!(changed.contains("            managed_directory: \"/o\","));
+        assert!(changed.contains("\n    managed_directory: \"/m\",\n"));
+
+        let toml = "[libraries.work]\nmanaged_directory = \"/w\"\n";
+        assert_eq!(
+            set_line(toml, "managed_directory", "\"/m\"", FileFormat::Toml),
+            "managed_directory = \"/m\"\n[libraries.work]\nmanaged_directory = \"/w\"\n"
+        );
+    }
+
+    #[test]
+    fn add_and_remove_libraries() {
+        for file_name in ["tagged.ron", "tagged.toml"] {
+            let location = location(&format!("libraries-{}", file_name), file_name);
+
+            add_library(&location, "work", Path::new("/work")).unwrap();
+            add_library(&location, "old", Path::new("/old")).unwrap();
+            assert_eq!(library_names(&location), ["old", "work"]);
+            assert!(matches!(
+                add_library(&location, "work", Path::new("/other")),
+                Err(Error::Invalid(_))
+            ));
+
+            remove_library(&location, "work").unwrap();
+            assert_eq!(library_names(&location), ["old"]);
+            assert!(matches!(
+                remove_library(&location, "work"),
+                Err(Error::NotFound(_))
+            ));
+
+            // the rest of the file stays, with its comments
+            let text = std::fs::read_to_string(&location.path).unwrap();
+            assert!(text.contains("the config of TaggedFiles"));
+            assert!(read(&location).is_ok());
+        }
+    }
+
+    #[test]
+    fn default_library_is_not_removed() {
+        let location = location("default-library", "tagged.ron");
+        add_library(&location, "work", Path::new("/work")).unwrap();
+        set(&location, "default_library", "work").unwrap();
+
+        assert!(matches!(
+            remove_library(&location, "work"),
+            Err(Error::Invalid(_))
+        ));
+        assert_eq!(library_names(&location), ["work"]);
+    }
 }
@@ -0,0 +1,259 @@
+use std::ffi::{OsStr, OsString};
+use std::path::{Component, Path, PathBuf};
+
+use super::style;
+use crate::error::Error;
+
+/// where files go inside the managed directory, like `{primary_tag}/{year}/{name}`
+#[derive(Clone, Debug)]
+pub struct Layout {
+    template: String,
+    parts: Vec<Part>,
+}
+
+#[derive(Clone, Debug)]
+enum Part {
+    Text(String),
+    Field(Field),
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Field {
+    /// the file name, with the extension
+    Name,
+    Stem,
+    /// without the dot, empty if there is none
+    Extension,
+    /// the first tag the file got, `untagged` if it has none
+    PrimaryTag,
+    Year,
+    Month,
+}
+impl Field {
+    const ALL: [(&'static str, Field); 6] = [
+        ("name", Field::Name),
+        ("stem", Field::Stem),
+        ("extension", Field::Extension),
+        ("primary_tag", Field::PrimaryTag),
+        ("year", Field::Year),
+        ("month", Field::Month),
+    ];
+}
+
+/// what the layout needs to know about a file
+pub struct Placement<'a> {
+    /// the file name on disk
+    pub name: &'a OsStr,
+    /// in the order the file got them
+    pub tags: &'a [String],
+    /// seconds since the unix epoch, `None` if it is unknown
+    pub modified: Option<u64>,
+}
+
+impl Default for Layout {
+    /// all files next to each other, like before layouts existed
+    fn default() -> Self {
+        Layout::parse("{name}").unwrap()
+    }
+}
+
+impl Layout {
+    pub fn parse(template: &str) -> Result<Layout, Error> {
+        let invalid =
+            |reason: String| Error::Config(format!("invalid layout '{}', {}", template, reason));
+
+        let path = Path::new(template);
+        if path
+            .components()
+            .any(|x| !matches!(x, Component::Normal(_)))
+        {
+            return Err(invalid(
+                "it has to be a relative path without `..`".to_owned(),
+            ));
+        }
+
+        let mut parts = vec![];
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_owned()));
+            }
+            let end = match rest[start..].find('}') {
+                Some(end) => start + end,
+                None => return Err(invalid(format!("`{}` is not closed", &rest[start..]))),
+            };
+            let name = &rest[start + 1..end];
+            match Field::ALL.iter().find(|x| x.0 == name) {
+                Some((_, field)) => parts.push(Part::Field(*field)),
+                None => {
+                    return Err(invalid(format!(
+                        "unknown placeholder {{{}}}, use one of {}",
+                        name,
+                        Field::ALL
+                            .iter()
+                            .map(|x| format!("{{{}}}", x.0))
+                            .collect::<Vec<String>>()
+                            .join(", ")
+                    )))
+                }
+            }
+            rest = &rest[end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_owned()));
+        }
+
+        if !parts
+            .iter()
+            .any(|x| matches!(x, Part::Field(Field::Name | Field::Stem)))
+        {
+            return Err(invalid(
+                "it needs {name} or {stem}, otherwise all files end up at the same path".to_owned(),
+            ));
+        }
+
+        Ok(Layout {
+            template: template.to_owned(),
+            parts,
+        })
+    }
+
+    pub fn template(&self) -> &str {
+        &self.template
+    }
+
+    /// the path of the file relative to the managed directory
+    pub fn path(&self, placement: &Placement) -> PathBuf {
+        let name = Path::new(placement.name);
+        let (year, month) = match placement.modified {
+            Some(modified) => {
+                let (year, month, _) = style::date(modified);
+                (format!("{:04}", year), format!("{:02}", month))
+            }
+            None => ("unknown".to_owned(), "unknown".to_owned()),
+        };
+
+        let mut path = OsString::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => path.push(text),
+                // the name is used as it is, so file names that are not valid utf-8 stay the same
+                Part::Field(Field::Name) => path.push(placement.name),
+                Part::Field(Field::Stem) => path.push(name.file_stem().unwrap_or(placement.name)),
+                Part::Field(Field::Extension) => path.push(name.extension().unwrap_or_default()),
+                Part::Field(Field::PrimaryTag) => path.push(component(
+                    placement.tags.first().map_or("untagged", |x| x.as_str()),
+                )),
+                Part::Field(Field::Year) => path.push(&year),
+                Part::Field(Field::Month) => path.push(&month),
+            }
+        }
+        PathBuf::from(path)
+    }
+}
+
+/// a value that can be used as one directory name, so a tag can not add directories or leave the managed directory
+fn component(value: &str) -> String {
+    let value = value.replace(['/', '\\'], "_");
+    if value.is_empty() || value == "." || value == ".." {
+        return "_".to_owned();
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rejected(template: &str, reason: &str) {
+        match Layout::parse(template) {
+            Err(Error::Config(message)) => assert!(
+                message.contains(reason),
+                "'{}' was rejected with '{}'",
+                template,
+                message
+            ),
+            Err(e) => panic!("'{}' was rejected with another error, {}", template, e),
+            Ok(_) => panic!("'{}' was accepted", template),
+        }
+    }
+
+    fn placed(template: &str, name: &str, tags: &[&str], modified: Option<u64>) -> PathBuf {
+        let tags: Vec<String> = tags.iter().map(|x| x.to_string()).collect();
+        Layout::parse(template).unwrap().path(&Placement {
+            name: OsStr::new(name),
+            tags: &tags,
+            modified,
+        })
+    }
+
+    #[test]
+    fn valid_templates() {
+        for template in [
+            "{name}",
+            "{stem}.{extension}",
+            "{primary_tag}/{year}/{month}/{name}",
+            "files/{stem}-copy.{extension}",
+        ] {
+            assert_eq!(Layout::parse(template).unwrap().template(), template);
+        }
+    }
+
+    #[test]
+    fn templates_that_leave_the_directory() {
+        rejected("/{name}", "relative path");
+        rejected("../{name}", "relative path");
+        rejected("{year}/../{name}", "relative path");
+        rejected("./{name}", "relative path");
+    }
+
+    #[test]
+    fn broken_placeholders() {
+        rejected("{name", "is not closed");
+        rejected("{year}/{name", "is not closed");
+        rejected("{tag}/{name}", "unknown placeholder {tag}");
+        rejected("{}/{name}", "unknown placeholder {}");
+    }
+
+    #[test]
+    fn templates_without_a_name() {
+        rejected("{primary_tag}/{year}", "needs {name} or {stem}");
+        rejected("{extension}", "needs {name} or {stem}");
+        rejected("files", "needs {name} or {stem}");
+    }
+
+    #[test]
+    fn paths() {
+        // 2021-03-04
+        let modified = Some(1614816000);
+        assert_eq!(
+            placed(
+                "{primary_tag}/{year}/{month}/{name}",
+                "a.txt",
+                &["work", "docs"],
+                modified
+            ),
+            Path::new("work/2021/03/a.txt")
+        );
+        assert_eq!(
+            placed("{primary_tag}/{year}/{name}", "a.txt", &[], None),
+            Path::new("untagged/unknown/a.txt")
+        );
+        assert_eq!(
+            placed("{stem}-old.{extension}", "a.tar.gz", &[], None),
+            Path::new("a.tar-old.gz")
+        );
+    }
+
+    #[test]
+    fn tags_stay_one_directory() {
+        assert_eq!(
+            placed("{primary_tag}/{name}", "a.txt", &["x/y"], None),
+            Path::new("x_y/a.txt")
+        );
+        assert_eq!(
+            placed("{primary_tag}/{name}", "a.txt", &[".."], None),
+            Path::new("_/a.txt")
+        );
+    }
+}
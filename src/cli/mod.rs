@@ -1,3 +1,12 @@
 pub mod config;
+pub mod layout;
+pub mod open;
+pub mod output;
 pub mod parse;
+pub mod picker;
 pub mod prompt;
+pub mod select;
+pub mod shell;
+pub mod style;
+pub mod table;
+pub mod tui;
@@ -0,0 +1,198 @@
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::path::Path;
+use std::process::Command;
+
+use crate::error::Error;
+use crate::tags::types::{escape_os_str, Tag};
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Open,
+    Edit,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    File,
+    Directory,
+}
+
+/// a rule from the `[[openers]]` tables in the config, the first rule where every given condition matches is used
+#[derive(Deserialize, Serialize, Clone)]
+pub struct OpenerRule {
+    /// the command to run, `{path}` is replaced by the path, otherwise the path is added as last argument
+    pub command: String,
+    /// `open` or `edit`, rules without it are used for `open`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub action: Option<Action>,
+    /// mime type guessed from the extension, `type/*` matches every subtype
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mime: Option<String>,
+    /// extensions without the leading dot
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extension: Option<Vec<String>>,
+    /// the file needs to have this tag
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    /// `file` or `directory`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<Kind>,
+    /// dont wait for the command to finish, for graphical programs
+    #[serde(default)]
+    pub detach: bool,
+}
+impl OpenerRule {
+    fn matches(&self, action: Action, path: &Path, tags: &[Tag]) -> bool {
+        if self.action.unwrap_or(Action::Open) != action {
+            return false;
+        }
+        if let Some(kind) = self.kind {
+            let is_directory = path.is_dir();
+            if (kind == Kind::Directory) != is_directory {
+                return false;
+            }
+        }
+        if let Some(tag) = &self.tag {
+            if !tags.iter().any(|x| &x.name == tag) {
+                return false;
+            }
+        }
+        if let Some(extensions) = &self.extension {
+            let extension = path
+                .extension()
+                .map(|x| x.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            if !extensions.iter().any(|x| x.to_lowercase() == extension) {
+                return false;
+            }
+        }
+        if let Some(mime) = &self.mime {
+            if path.is_dir() || !mime_matches(mime, path) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+fn mime_matches(pattern: &str, path: &Path) -> bool {
+    let guessed = match mime_guess::from_path(path).first() {
+        Some(guessed) => guessed,
+        None => return false,
+    };
+    match pattern.split_once('/') {
+        Some((kind, "*")) => guessed.type_().as_str() == kind,
+        _ => guessed.essence_str() == pattern,
+    }
+}
+
+/// what would be run to open a file, and if it should be waited on
+pub struct Opener {
+    pub command: Vec<OsString>,
+    pub detach: bool,
+}
+impl Opener {
+    pub fn find(
+        rules: &[OpenerRule],
+        action: Action,
+        path: &Path,
+        tags: &[Tag],
+    ) -> Result<Opener, Error> {
+        let (command, detach) = match rules.iter().find(|x| x.matches(action, path, tags)) {
+            Some(rule) => (rule.command.clone(), rule.detach),
+            None => (fallback(action), false),
+        };
+
+        let words = match shell_words::split(&command) {
+            Ok(words) => words,
+            Err(e) => {
+                return Err(Error::Config(format!(
+                    "could not parse opener command '{}', {}",
+                    command, e
+                )))
+            }
+        };
+        if words.is_empty() {
+            return Err(Error::Config("opener command is empty".to_owned()));
+        }
+
+        // the path is put in as it is, it does not have to be valid utf-8
+        let mut command: Vec<OsString> = words.iter().map(|x| with_path(x, path)).collect();
+        if !words.iter().any(|x| x.contains("{path}")) {
+            command.push(path.as_os_str().to_owned());
+        }
+
+        Ok(Opener { command, detach })
+    }
+
+    /// the command as it could be pasted in a shell
+    pub fn display(&self) -> String {
+        shell_words::join(self.command.iter().map(|x| escape_os_str(x)))
+    }
+
+    fn program(&self) -> String {
+        escape_os_str(&self.command[0])
+    }
+
+    pub fn run(&self) -> Result<(), Error> {
+        let mut command = Command::new(&self.command[0]);
+        command.args(&self.command[1..]);
+
+        if self.detach {
+            if let Err(e) = command.spawn() {
+                return Err(Error::Other(format!(
+                    "could not start '{}', {}",
+                    self.program(),
+                    e
+                )));
+            }
+            return Ok(());
+        }
+
+        match command.status() {
+            Ok(status) if status.success() => Ok(()),
+            Ok(status) => Err(Error::Other(format!(
+                "'{}' exited with {}",
+                self.program(),
+                status
+            ))),
+            Err(e) => Err(Error::Other(format!(
+                "could not start '{}', {}",
+                self.program(),
+                e
+            ))),
+        }
+    }
+}
+
+/// the word with every `{path}` replaced by the path
+fn with_path(word: &str, path: &Path) -> OsString {
+    let mut result = OsString::new();
+    for (i, part) in word.split("{path}").enumerate() {
+        if i > 0 {
+            result.push(path.as_os_str());
+        }
+        result.push(part);
+    }
+    result
+}
+
+fn fallback(action: Action) -> String {
+    match action {
+        Action::Open => {
+            if cfg!(target_os = "macos") {
+                "open".to_owned()
+            } else {
+                "xdg-open".to_owned()
+            }
+        }
+        Action::Edit => std::env::var("VISUAL")
+            .or(std::env::var("EDITOR"))
+            .ok()
+            .filter(|x| !x.is_empty())
+            .unwrap_or("vi".to_owned()),
+    }
+}
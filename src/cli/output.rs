@@ -0,0 +1,377 @@
+use clap::ValueEnum;
+use ron::ser::{to_string_pretty, PrettyConfig};
+use serde::Serialize;
+use std::ffi::OsString;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use super::style;
+use crate::error::Error;
+use crate::tags::types::{StoredFile, Tag};
+
+/// how results are written to stdout, everything except `Text` is meant for scripts
+#[derive(ValueEnum, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// human readable, may be coloured and aligned
+    Text,
+    /// one json array with all results
+    Json,
+    /// one json object per line
+    Jsonl,
+    /// tab separated fields, one result per line, in the documented field order
+    Tsv,
+    /// the same fields as tsv with a header line, quoted where needed
+    Csv,
+    /// one ron list with all results
+    Ron,
+    /// only the path (or name for tags), seperated by NUL characters, for `xargs -0`
+    Null,
+}
+
+/// something that can be printed in every output format
+pub trait Record: Serialize {
+    /// the field names and their values as text, in a stable order
+    fn fields(&self) -> Vec<(&'static str, String)>;
+
+    /// the single value that identifies this record, used by `--format null`
+    fn key(&self) -> OsString;
+}
+
+#[derive(Serialize)]
+pub struct FileRecord {
+    pub name: String,
+    /// bytes that are not valid utf-8 are escaped, see `style::escape_path`
+    pub path: String,
+    /// the path as it is, for `--format null`
+    #[serde(skip)]
+    pub raw_path: PathBuf,
+    pub tags: Vec<String>,
+    pub exists: bool,
+    pub last_used: u64,
+    pub library: String,
+}
+impl FileRecord {
+    pub fn new(file: &StoredFile, tags: &[Tag], library: &str) -> FileRecord {
+        FileRecord {
+            name: file.name.clone(),
+            path: style::escape_path(&file.path),
+            raw_path: file.path.clone(),
+            tags: tags.iter().map(|x| x.name.clone()).collect(),
+            exists: file.path.exists(),
+            last_used: file.last_used,
+            library: library.to_owned(),
+        }
+    }
+}
+impl Record for FileRecord {
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("name", self.name.clone()),
+            ("path", self.path.clone()),
+            ("tags", self.tags.join(",")),
+            ("exists", self.exists.to_string()),
+            ("last_used", self.last_used.to_string()),
+            ("library", self.library.clone()),
+        ]
+    }
+
+    fn key(&self) -> OsString {
+        self.raw_path.clone().into_os_string()
+    }
+}
+
+#[derive(Serialize)]
+pub struct TagRecord {
+    pub name: String,
+    pub description: Option<String>,
+    pub color: Option<String>,
+    pub icon: Option<String>,
+    pub children: Vec<String>,
+    pub files: usize,
+}
+impl TagRecord {
+    pub fn new(tag: &Tag, files: usize) -> TagRecord {
+        TagRecord {
+            name: tag.name.clone(),
+            description: tag.description.clone(),
+            color: tag.color.map(|x| x.name().to_owned()),
+            icon: tag.icon.clone(),
+            children: tag.children.iter().map(|x| x.name.clone()).collect(),
+            files,
+        }
+    }
+}
+impl Record for TagRecord {
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("name", self.name.clone()),
+            ("description", self.description.clone().unwrap_or_default()),
+            ("color", self.color.clone().unwrap_or_default()),
+            ("icon", self.icon.clone().unwrap_or_default()),
+            ("children", self.children.join(",")),
+            ("files", self.files.to_string()),
+        ]
+    }
+
+    fn key(&self) -> OsString {
+        OsString::from(&self.name)
+    }
+}
+
+/// one change in the journal, see `history`
+#[derive(Serialize)]
+pub struct HistoryRecord {
+    /// counts from 1, the oldest change first
+    pub index: usize,
+    pub time: u64,
+    pub command: String,
+    pub changes: String,
+    pub undone: bool,
+}
+impl Record for HistoryRecord {
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("index", self.index.to_string()),
+            ("time", self.time.to_string()),
+            ("command", self.command.clone()),
+            ("changes", self.changes.clone()),
+            ("undone", self.undone.to_string()),
+        ]
+    }
+
+    fn key(&self) -> OsString {
+        OsString::from(self.index.to_string())
+    }
+}
+
+/// see `libraries list`
+#[derive(Serialize)]
+pub struct LibraryRecord {
+    pub name: String,
+    pub managed_directory: String,
+    pub database_file: String,
+    /// `None` if the database does not exist yet
+    pub files: Option<usize>,
+    /// used without `--library`
+    pub default: bool,
+}
+impl Record for LibraryRecord {
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("name", self.name.clone()),
+            ("managed_directory", self.managed_directory.clone()),
+            ("database_file", self.database_file.clone()),
+            (
+                "files",
+                self.files.map(|x| x.to_string()).unwrap_or_default(),
+            ),
+            ("default", self.default.to_string()),
+        ]
+    }
+
+    fn key(&self) -> OsString {
+        OsString::from(&self.name)
+    }
+}
+
+/// one value of the config, see `config show`
+#[derive(Serialize)]
+pub struct ConfigRecord {
+    pub key: String,
+    pub value: String,
+    /// `default`, `config`, or the flag or environment variable that set it
+    pub source: String,
+}
+impl Record for ConfigRecord {
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("key", self.key.clone()),
+            ("value", self.value.clone()),
+            ("source", self.source.clone()),
+        ]
+    }
+
+    fn key(&self) -> OsString {
+        OsString::from(&self.key)
+    }
+}
+
+/// everything known about one path, see `info`
+#[derive(Serialize)]
+pub struct InfoRecord {
+    pub name: String,
+    pub path: String,
+    #[serde(skip)]
+    pub raw_path: PathBuf,
+    pub tracked: bool,
+    pub tags: Vec<String>,
+    /// the children the tags of the file have, files cant be given a child tag themselves
+    pub tag_children: Vec<String>,
+    pub last_used: u64,
+    pub exists: bool,
+    pub size: Option<u64>,
+    pub modified: Option<u64>,
+    pub views: Vec<String>,
+    /// how the file was added, see `AddMode`
+    pub mode: String,
+    /// where the file was before it was added, if it was put in the managed directory
+    pub original: Option<String>,
+}
+impl Record for InfoRecord {
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("name", self.name.clone()),
+            ("path", self.path.clone()),
+            ("tracked", self.tracked.to_string()),
+            ("tags", self.tags.join(",")),
+            ("tag_children", self.tag_children.join(",")),
+            ("last_used", self.last_used.to_string()),
+            ("exists", self.exists.to_string()),
+            ("size", self.size.map(|x| x.to_string()).unwrap_or_default()),
+            (
+                "modified",
+                self.modified.map(|x| x.to_string()).unwrap_or_default(),
+            ),
+            ("views", self.views.join(",")),
+            ("mode", self.mode.clone()),
+            ("original", self.original.clone().unwrap_or_default()),
+        ]
+    }
+
+    fn key(&self) -> OsString {
+        self.raw_path.clone().into_os_string()
+    }
+}
+
+pub struct Output {
+    format: Format,
+    template: Option<String>,
+}
+impl Output {
+    pub fn new(format: Format, template: Option<String>) -> Output {
+        Output { format, template }
+    }
+
+    /// the format the results are written in
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    pub fn is_text(&self) -> bool {
+        self.format == Format::Text && self.template.is_none()
+    }
+
+    pub fn records<R: Record>(&self, records: &[R]) -> Result<(), Error> {
+        let mut stdout = io::stdout().lock();
+        let result = match (&self.template, self.format) {
+            (Some(template), _) => records
+                .iter()
+                .try_for_each(|x| writeln!(stdout, "{}", fill_template(template, &x.fields()))),
+            (None, Format::Json) => {
+                let json = serde_json::to_string_pretty(records).map_err(io::Error::other)?;
+                writeln!(stdout, "{}", json)
+            }
+            (None, Format::Jsonl) => records.iter().try_for_each(|x| {
+                let json = serde_json::to_string(x).map_err(io::Error::other)?;
+                writeln!(stdout, "{}", json)
+            }),
+            (None, Format::Tsv) => records.iter().try_for_each(|x| {
+                let fields: Vec<String> = x
+                    .fields()
+                    .into_iter()
+                    .map(|(_, value)| escape_tsv(&value))
+                    .collect();
+                writeln!(stdout, "{}", fields.join("\t"))
+            }),
+            (None, Format::Csv) => {
+                let mut writer = csv::Writer::from_writer(&mut stdout);
+                if let Some(first) = records.first() {
+                    let names: Vec<&str> = first.fields().iter().map(|x| x.0).collect();
+                    writer.write_record(names).map_err(io::Error::other)?;
+                }
+                for record in records {
+                    let values: Vec<String> = record.fields().into_iter().map(|x| x.1).collect();
+                    writer.write_record(values).map_err(io::Error::other)?;
+                }
+                writer.flush()
+            }
+            (None, Format::Ron) => {
+                let ron = to_string_pretty(records, PrettyConfig::new().indentor("  ".to_owned()))
+                    .map_err(io::Error::other)?;
+                writeln!(stdout, "{}", ron)
+            }
+            (None, Format::Null) => records.iter().try_for_each(|x| {
+                stdout.write_all(x.key().as_encoded_bytes())?;
+                stdout.write_all(b"\0")
+            }),
+            (None, Format::Text) => records
+                .iter()
+                .try_for_each(|x| writeln!(stdout, "{}", style::escape_path(Path::new(&x.key())))),
+        };
+
+        match result {
+            // the reader stopped early, like `head` does
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+            Err(e) => Err(e.into()),
+            Ok(()) => Ok(()),
+        }
+    }
+
+    /// prints the error to stderr, as a json object when the output is json so scripts can read it
+    pub fn error(&self, error: &Error) {
+        match (&self.template, self.format) {
+            (None, Format::Json) | (None, Format::Jsonl) => {
+                let json = serde_json::json!({
+                    "error": {
+                        "kind": error.kind(),
+                        "message": error.message(),
+                        "code": error.code(),
+                    }
+                });
+                eprintln!("{}", json);
+            }
+            _ => eprintln!("Something went wrong, {}", error),
+        }
+    }
+}
+
+/// replaces `{field}` with the value of that field, and `\t`, `\n`, `\0` and `\\` with the characters they stand for
+fn fill_template(template: &str, fields: &[(&'static str, String)]) -> String {
+    let mut result = String::new();
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('t') => result.push('\t'),
+                Some('n') => result.push('\n'),
+                Some('0') => result.push('\0'),
+                Some(other) => result.push(other),
+                None => result.push('\\'),
+            },
+            '{' => {
+                let mut name = String::new();
+                for next in chars.by_ref() {
+                    if next == '}' {
+                        break;
+                    }
+                    name.push(next);
+                }
+                match fields.iter().find(|(field, _)| *field == name) {
+                    Some((_, value)) => result.push_str(value),
+                    // unknown fields are left as they are, so a typo is easy to spot
+                    None => result.push_str(&format!("{{{}}}", name)),
+                }
+            }
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+fn escape_tsv(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
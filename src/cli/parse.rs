@@ -1,43 +1,200 @@
+use std::ffi::OsString;
 use std::path::PathBuf;
 
-use clap::{Parser, Subcommand, ValueEnum};
+use crate::tags::exchange::{Encoding, Merge, Rewrite};
+use crate::tags::types::AddMode;
+
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+
+use super::output::Format;
+use super::shell::Shell;
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
-struct Cli {
+pub struct Cli {
     // TODO add debug or verbose option or whatever here
     #[command(subcommand)]
-    command: SubCommands,
+    pub command: SubCommands,
+
+    /// output format, see the README for the fields of every format
+    #[arg(long, global = true, value_enum, default_value_t = Format::Text)]
+    pub format: Format,
+
+    /// print every result with this template instead, e.g. '{name}\t{path}\t{tags}'
+    #[arg(long, global = true)]
+    pub template: Option<String>,
+
+    /// use this config file instead of the one in the config directory
+    #[arg(long, global = true, env = "TAGGEDFILES_CONFIG", value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// use this library from the config instead of the default one
+    #[arg(long, global = true, value_name = "NAME")]
+    pub library: Option<String>,
+
+    /// use this database file instead of the one from the config
+    #[arg(long, global = true, value_name = "PATH")]
+    pub db: Option<PathBuf>,
 }
 
 #[derive(Subcommand)]
 pub enum SubCommands {
     // TODO add value_name to most of these
     /// list all files and what tags they have
-    Listfiles,
+    Listfiles {
+        /// only list files matching these tags
+        query: Option<Vec<String>>,
+
+        /// column to sort on
+        #[arg(short, long, value_enum, default_value_t = FileSort::Name)]
+        sort: FileSort,
+
+        /// reverse the sort order
+        #[arg(short, long, default_value_t = false)]
+        reverse: bool,
+
+        /// which columns to show, comma separated
+        #[arg(short, long, value_enum, value_delimiter = ',', default_values_t = [FileColumn::Name, FileColumn::Tags, FileColumn::Path, FileColumn::Exists, FileColumn::LastUsed])]
+        columns: Vec<FileColumn>,
+
+        /// dont print the column names
+        #[arg(long, default_value_t = false)]
+        no_header: bool,
+
+        /// list the files of every library, with a library column
+        #[arg(long)]
+        all_libraries: bool,
+    },
 
     /// list all tags and what files have them
-    Listtags,
+    Listtags {
+        /// only list tags whose name contains this text
+        query: Option<String>,
+
+        /// column to sort on
+        #[arg(short, long, value_enum, default_value_t = TagSort::Name)]
+        sort: TagSort,
+
+        /// reverse the sort order
+        #[arg(short, long, default_value_t = false)]
+        reverse: bool,
+
+        /// which columns to show, comma separated
+        #[arg(short, long, value_enum, value_delimiter = ',', default_values_t = [TagColumn::Name, TagColumn::Files, TagColumn::Children, TagColumn::Description])]
+        columns: Vec<TagColumn>,
+
+        /// dont print the column names
+        #[arg(long, default_value_t = false)]
+        no_header: bool,
+    },
 
     /// search file based on tags and opens a prompt if multiple files match, prints absolute path to stdout
     Getfile {
         /// tags to search on, if none given will return all files
         tags: Option<Vec<String>>,
 
-        /// Instead of asking which specific file you want, it will just spit them all out seperated by spaces
+        /// Instead of asking which specific file you want, it will just spit them all out, one per line
         #[arg(long, default_value_t = false)]
         multiple: bool,
+
+        /// choose from the files of every library
+        #[arg(long)]
+        all_libraries: bool,
+    },
+
+    /// choose a file like getfile and open it with the opener from the config, xdg-open by default
+    Open {
+        /// tags to search on, if none given will choose from all files
+        tags: Option<Vec<String>>,
+
+        /// only print the command that would be run
+        #[arg(long, default_value_t = false)]
+        print_command: bool,
+    },
+
+    /// choose a file like getfile and open it in the editor from the config, $EDITOR by default
+    Edit {
+        /// tags to search on, if none given will choose from all files
+        tags: Option<Vec<String>>,
+
+        /// only print the command that would be run
+        #[arg(long, default_value_t = false)]
+        print_command: bool,
+    },
+
+    /// mark several files in a prompt and do the same thing with all of them
+    Select {
+        /// tags to search on, if none given will choose from all files
+        tags: Option<Vec<String>>,
+
+        /// what to do with the chosen files, asked after choosing if not given
+        #[arg(short, long, value_enum)]
+        action: Option<BulkAction>,
+
+        /// tag to add or remove with the tag and untag actions, can be given multiple times
+        #[arg(long = "tag", value_name = "TAG")]
+        change_tags: Vec<String>,
+
+        /// fail when a tag does not exist, instead of asking to create it
+        #[arg(long, default_value_t = false, conflicts_with = "create_tags")]
+        strict: bool,
+
+        /// create tags that do not exist yet without asking
+        #[arg(long, default_value_t = false)]
+        create_tags: bool,
+    },
+
+    /// full screen view to tag many files at once
+    Tui,
+
+    /// show everything known about a single file, also works for files that are not tagged
+    Info {
+        /// path or name of the file
+        #[arg(required = true)]
+        file: OsString,
     },
 
     /// add a new file to the tagged files
     Addfile {
-        /// path of the file to add
-        #[arg(required = true)]
-        file_path: PathBuf,
+        /// paths or glob patterns of the files to add
+        #[arg(required_unless_present = "stdin")]
+        paths: Vec<OsString>,
 
         /// how the file will be added to the tagged files
         #[arg(short, long, value_enum, default_value_t = AddFileOptions::None)]
         option: AddFileOptions,
+
+        /// what to do when a moved file already exists in the managed directory, asks by default
+        #[arg(long, value_enum)]
+        on_conflict: Option<Conflict>,
+
+        /// tags to give all added files
+        #[arg(short, long, num_args = 1..)]
+        tags: Vec<String>,
+
+        /// add the files inside directories instead of the directories themselves
+        #[arg(short, long, default_value_t = false)]
+        recursive: bool,
+
+        /// with --recursive, only add files matching one of these glob patterns, e.g. '*.pdf'
+        #[arg(long)]
+        include: Vec<String>,
+
+        /// with --recursive, skip files and directories matching one of these glob patterns
+        #[arg(long)]
+        exclude: Vec<String>,
+
+        /// also read paths from stdin, one per line, e.g. `fd -e pdf | TaggedFiles addfile --stdin --tags docs`
+        #[arg(long, default_value_t = false)]
+        stdin: bool,
+
+        /// fail when a tag does not exist, instead of asking to create it
+        #[arg(long, default_value_t = false, conflicts_with = "create_tags")]
+        strict: bool,
+
+        /// create tags that do not exist yet without asking
+        #[arg(long, default_value_t = false)]
+        create_tags: bool,
     },
 
     /// add a tag
@@ -48,22 +205,49 @@ pub enum SubCommands {
         names: Vec<String>,
     },
 
+    /// change the description, colour or icon of a tag
+    Edittag {
+        /// name of the tag to edit
+        #[arg(required = true)]
+        name: String,
+
+        /// short description of what the tag is for, an empty string removes it
+        #[arg(short, long)]
+        description: Option<String>,
+
+        /// one of black, red, green, yellow, blue, magenta, cyan or white, an empty string removes it
+        #[arg(short, long)]
+        color: Option<String>,
+
+        /// icon or emoji shown in front of the tag, an empty string removes it
+        #[arg(short, long)]
+        icon: Option<String>,
+    },
+
     /// set tags for a file
     Settags {
         /// file that a tag should be assigned to
         #[arg(required = true)]
-        file: String,
+        file: OsString,
 
         /// tags to be assigned to the file
         #[arg(required = true)]
         tags: Vec<String>,
+
+        /// fail when a tag does not exist, instead of asking to create it
+        #[arg(long, default_value_t = false, conflicts_with = "create_tags")]
+        strict: bool,
+
+        /// create tags that do not exist yet without asking
+        #[arg(long, default_value_t = false)]
+        create_tags: bool,
     },
 
     /// remove a file
     Removefile {
-        /// all file names to be removed
+        /// paths or names of all files to be removed
         #[arg(required = true)]
-        names: Vec<String>,
+        names: Vec<OsString>,
     },
 
     /// remove a tag, also removes tag from all files
@@ -73,6 +257,114 @@ pub enum SubCommands {
         names: Vec<String>,
     },
 
+    /// revert the last change to the database, also moves files back that were moved by addfile
+    Undo,
+
+    /// do the last undone change again
+    Redo,
+
+    /// list the changes that can be undone, the newest last
+    History {
+        /// how many changes to show
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+    },
+
+    /// merge files that are stored more than once and remove repeated tags, for databases made by older versions
+    Dedupe {
+        /// only report what would be merged
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// move the files in the managed directory to where the layout in the config puts them, e.g. after their tags changed
+    Reorganize {
+        /// only show what would be moved
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// print the whole database as json, csv or ron depending on --format, json by default
+    Export,
+
+    /// read files and tags from an export, tmsu or tagspaces and merge them into the database
+    #[command(subcommand_negates_reqs = true, args_conflicts_with_subcommands = true)]
+    Import {
+        #[command(subcommand)]
+        source: Option<ImportSource>,
+
+        /// the export to read, - for stdin
+        #[arg(required = true)]
+        file: Option<PathBuf>,
+
+        /// the format of the export, guessed from the extension or the content if not given
+        #[arg(long, value_enum)]
+        from: Option<ImportFormat>,
+
+        /// what to do with the files and tags that are already in the database
+        #[arg(short, long, value_enum, global = true, default_value_t = MergeStrategy::Union)]
+        strategy: MergeStrategy,
+
+        /// replace the start of imported paths, e.g. /home/old=/home/new, can be given multiple times
+        #[arg(long, value_name = "OLD=NEW", global = true)]
+        rewrite: Vec<Rewrite>,
+
+        /// only report what would change
+        #[arg(long, global = true)]
+        dry_run: bool,
+    },
+
+    /// list stored files that are gone, and pick up the tags in the .tagged.ron sidecars under these directories
+    Check {
+        /// directories to search for sidecars, the managed directory by default
+        roots: Vec<PathBuf>,
+
+        /// only show what would change
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// bring the tags in the database and in the user.xdg.tags extended attribute of every file together
+    SyncXattr {
+        /// files or directories to search for files that other programs tagged, they are added without moving them
+        paths: Vec<PathBuf>,
+
+        /// which tags a file gets when the database and the attribute disagree
+        #[arg(long, value_enum, default_value_t = XattrPrefer::Union)]
+        prefer: XattrPrefer,
+
+        /// only show what would change
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// list, create or remove libraries, each has its own managed directory and database
+    Libraries {
+        #[command(subcommand)]
+        action: LibraryAction,
+    },
+
+    /// show or change the config
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+
+    /// print a shell function and completions to source in your shell config, e.g. `eval "$(TaggedFiles init bash)"`
+    Init {
+        /// shell to generate the script for
+        #[arg(value_enum)]
+        shell: Shell,
+
+        /// name of the shell function, it provides `cd` on top of all normal subcommands
+        #[arg(long, default_value = "tf")]
+        cmd: String,
+    },
+
+    /// print the preview shown next to the file list, used by external pickers
+    #[command(hide = true)]
+    Preview { path: PathBuf },
+
     // TODO
     /// add a file to the database that is in the tagged directory
     AddUnstoredFile,
@@ -91,17 +383,238 @@ pub enum SubCommands {
     },
 }
 
-#[derive(ValueEnum, Clone)]
+#[derive(ValueEnum, Clone, Copy)]
 pub enum AddFileOptions {
+    /// leave the file where it is
     None,
+    /// move the file into the managed directory
     Move,
+    /// copy the file into the managed directory, the original stays where it is
+    Copy,
+    /// move the file into the managed directory and leave a symlink to it at the old path
+    Link,
+    /// hard link the file into the managed directory, only works on the same filesystem
+    Hardlink,
+}
+impl AddFileOptions {
+    pub fn mode(&self) -> AddMode {
+        match self {
+            AddFileOptions::None => AddMode::None,
+            AddFileOptions::Move => AddMode::Move,
+            AddFileOptions::Copy => AddMode::Copy,
+            AddFileOptions::Link => AddMode::Link,
+            AddFileOptions::Hardlink => AddMode::Hardlink,
+        }
+    }
+}
+
+/// other programs to import tags from
+#[derive(Subcommand)]
+pub enum ImportSource {
+    /// the sqlite database of tmsu, usually .tmsu/db, tags with a value like year=2017 become `year` and `year:2017`
+    Tmsu { database: PathBuf },
+    /// a directory tagged by tagspaces, tags are read from names like beach[summer 2020].jpg and the .ts sidecars
+    Tagspaces { directory: PathBuf },
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+pub enum MergeStrategy {
+    /// throw away the files and tags in the database, keep only the imported ones
+    Replace,
+    /// keep both, the database wins where they disagree
+    Union,
+    /// keep both, the import wins where they disagree and files get exactly the imported tags
+    PreferIncoming,
+}
+impl MergeStrategy {
+    pub fn merge(&self) -> Merge {
+        match self {
+            MergeStrategy::Replace => Merge::Replace,
+            MergeStrategy::Union => Merge::Union,
+            MergeStrategy::PreferIncoming => Merge::PreferIncoming,
+        }
+    }
 }
 
-pub fn parse() -> SubCommands {
-    let cli = Cli::parse();
+#[derive(ValueEnum, Clone, Copy, PartialEq)]
+pub enum XattrPrefer {
+    /// the tags from both
+    Union,
+    /// the tags from the database, the attribute is overwritten
+    Database,
+    /// the tags from the attribute, the database is changed
+    Xattr,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+pub enum ImportFormat {
+    Json,
+    Csv,
+    Ron,
+}
+impl ImportFormat {
+    pub fn encoding(&self) -> Encoding {
+        match self {
+            ImportFormat::Json => Encoding::Json,
+            ImportFormat::Csv => Encoding::Csv,
+            ImportFormat::Ron => Encoding::Ron,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+pub enum LibraryAction {
+    /// list every library from the config
+    List,
+    /// add a library to the config
+    Create {
+        name: String,
+        /// its managed directory, ~/tagged-<name> by default
+        #[arg(long)]
+        directory: Option<PathBuf>,
+    },
+    /// remove a library from the config, its files and database are kept
+    Remove { name: String },
+}
+
+#[derive(Subcommand)]
+pub enum ConfigAction {
+    /// print every value that is used, and which of them are defaults
+    Show,
+    /// print one value
+    Get {
+        /// e.g. managed_directory, see `config show` for all keys
+        key: String,
+    },
+    /// change one value, the comments in the config file are kept
+    Set { key: String, value: String },
+    /// open the config in $EDITOR and check it afterwards
+    Edit,
+    /// print where the config file is
+    Path,
+}
+
+/// what to do when the destination of a move already exists
+#[derive(ValueEnum, Clone, Copy)]
+pub enum Conflict {
+    /// leave the file where it is and dont add it
+    Skip,
+    /// move it under a free name like `name (1).ext`
+    Rename,
+    /// replace the existing file
+    Overwrite,
+}
+impl Conflict {
+    pub const ALL: [Conflict; 3] = [Conflict::Skip, Conflict::Rename, Conflict::Overwrite];
+}
+impl std::fmt::Display for Conflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match self {
+            Conflict::Skip => "skip",
+            Conflict::Rename => "keep both, rename the new file",
+            Conflict::Overwrite => "overwrite",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+/// what to do with tags that are used but do not exist yet
+#[derive(Clone, Copy, PartialEq)]
+pub enum UnknownTags {
+    /// ask the user, or skip them when there is no terminal
+    Ask,
+    Fail,
+    Create,
+}
+impl UnknownTags {
+    pub fn from_flags(strict: bool, create_tags: bool) -> UnknownTags {
+        match (strict, create_tags) {
+            (true, _) => UnknownTags::Fail,
+            (false, true) => UnknownTags::Create,
+            (false, false) => UnknownTags::Ask,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+pub enum BulkAction {
+    /// print the paths, respects --format
+    Print,
+    /// open every file with its opener
+    Open,
+    /// add tags
+    Tag,
+    /// remove tags
+    Untag,
+    /// remove the files from the database
+    Remove,
+    /// add symlinks to the files to the link directory
+    Link,
+}
+impl BulkAction {
+    pub const ALL: [BulkAction; 6] = [
+        BulkAction::Print,
+        BulkAction::Open,
+        BulkAction::Tag,
+        BulkAction::Untag,
+        BulkAction::Remove,
+        BulkAction::Link,
+    ];
+}
+impl std::fmt::Display for BulkAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match self {
+            BulkAction::Print => "print paths",
+            BulkAction::Open => "open",
+            BulkAction::Tag => "add tags",
+            BulkAction::Untag => "remove tags",
+            BulkAction::Remove => "remove from database",
+            BulkAction::Link => "add to link directory",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+#[derive(ValueEnum, Clone, PartialEq)]
+pub enum FileColumn {
+    Name,
+    Tags,
+    Path,
+    Exists,
+    LastUsed,
+    Library,
+}
+
+#[derive(ValueEnum, Clone)]
+pub enum FileSort {
+    Name,
+    Path,
+    LastUsed,
+    Tags,
+}
+
+#[derive(ValueEnum, Clone, PartialEq)]
+pub enum TagColumn {
+    Name,
+    Files,
+    Children,
+    Description,
+}
+
+#[derive(ValueEnum, Clone)]
+pub enum TagSort {
+    Name,
+    Files,
+}
+
+/// the clap definition of the cli, used to generate shell completions
+pub fn command() -> clap::Command {
+    Cli::command()
+}
 
+pub fn parse() -> Cli {
     // TODO check of paths are valid or other validation that clap cant do
     // maybe this could be moved to another validate.rs file
 
-    cli.command
+    Cli::parse()
 }
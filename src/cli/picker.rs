@@ -0,0 +1,290 @@
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::style::Print;
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use std::fs;
+use std::io::{self, Read, Stderr, Write};
+use std::path::{Path, PathBuf};
+
+use super::style;
+
+/// one line in the picker
+pub struct Candidate {
+    /// the line as shown, may contain colours
+    pub label: String,
+    /// the text the query is matched against
+    pub search: String,
+    /// the path shown in the preview pane
+    pub path: PathBuf,
+}
+
+/// restores the terminal when the picker is done, also when it returns early with an error
+pub struct TerminalGuard {
+    pub stderr: Stderr,
+}
+impl TerminalGuard {
+    pub fn new() -> io::Result<TerminalGuard> {
+        let mut stderr = io::stderr();
+        terminal::enable_raw_mode()?;
+        execute!(stderr, EnterAlternateScreen, Hide)?;
+        Ok(TerminalGuard { stderr })
+    }
+}
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = execute!(self.stderr, Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+struct State {
+    multi: bool,
+    /// candidates marked with tab in multi mode
+    marked: Vec<usize>,
+    query: String,
+    /// indexes into the candidates, best match first
+    matches: Vec<usize>,
+    selected: usize,
+    scroll: usize,
+}
+
+/// a fuzzy finder drawn on stderr, returns the indexes of the chosen candidates or None if cancelled
+///
+/// in multi mode tab marks candidates, enter returns the marked ones or the selected one if none are marked
+pub fn pick(prompt: &str, candidates: &[Candidate], multi: bool) -> io::Result<Option<Vec<usize>>> {
+    let mut guard = TerminalGuard::new()?;
+    let matcher = SkimMatcherV2::default();
+    let mut state = State {
+        multi,
+        marked: vec![],
+        query: String::new(),
+        matches: (0..candidates.len()).collect(),
+        selected: 0,
+        scroll: 0,
+    };
+
+    loop {
+        draw(&mut guard.stderr, prompt, candidates, &mut state)?;
+
+        let key = match event::read()? {
+            Event::Key(key) => key,
+            _ => continue,
+        };
+        match key {
+            KeyEvent {
+                code: KeyCode::Enter,
+                ..
+            } => {
+                if !state.marked.is_empty() {
+                    return Ok(Some(state.marked));
+                }
+                return Ok(state.matches.get(state.selected).map(|x| vec![*x]));
+            }
+            KeyEvent {
+                code: KeyCode::Tab, ..
+            } if state.multi => {
+                if let Some(i) = state.matches.get(state.selected) {
+                    match state.marked.iter().position(|x| x == i) {
+                        Some(position) => _ = state.marked.remove(position),
+                        None => state.marked.push(*i),
+                    }
+                }
+                state.selected = (state.selected + 1).min(state.matches.len().saturating_sub(1));
+            }
+            KeyEvent {
+                code: KeyCode::Esc, ..
+            } => return Ok(None),
+            KeyEvent {
+                code: KeyCode::Char('c'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => return Ok(None),
+            KeyEvent {
+                code: KeyCode::Up, ..
+            }
+            | KeyEvent {
+                code: KeyCode::BackTab,
+                ..
+            }
+            | KeyEvent {
+                code: KeyCode::Char('p'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => state.selected = state.selected.saturating_sub(1),
+            KeyEvent {
+                code: KeyCode::Down,
+                ..
+            }
+            | KeyEvent {
+                code: KeyCode::Tab, ..
+            }
+            | KeyEvent {
+                code: KeyCode::Char('n'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => state.selected = (state.selected + 1).min(state.matches.len().saturating_sub(1)),
+            KeyEvent {
+                code: KeyCode::Backspace,
+                ..
+            } => {
+                state.query.pop();
+                filter(&matcher, candidates, &mut state);
+            }
+            KeyEvent {
+                code: KeyCode::Char('u'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => {
+                state.query.clear();
+                filter(&matcher, candidates, &mut state);
+            }
+            KeyEvent {
+                code: KeyCode::Char(c),
+                modifiers,
+                ..
+            } if !modifiers.contains(KeyModifiers::CONTROL) => {
+                state.query.push(c);
+                filter(&matcher, candidates, &mut state);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn filter(matcher: &SkimMatcherV2, candidates: &[Candidate], state: &mut State) {
+    let mut scored: Vec<(i64, usize)> = candidates
+        .iter()
+        .enumerate()
+        .filter_map(|(i, x)| {
+            matcher
+                .fuzzy_match(&x.search, &state.query)
+                .map(|score| (score, i))
+        })
+        .collect();
+    // a stable sort keeps the frecency order for equal scores
+    scored.sort_by_key(|x| std::cmp::Reverse(x.0));
+
+    state.matches = scored.into_iter().map(|x| x.1).collect();
+    state.selected = 0;
+    state.scroll = 0;
+}
+
+fn draw(
+    stderr: &mut Stderr,
+    prompt: &str,
+    candidates: &[Candidate],
+    state: &mut State,
+) -> io::Result<()> {
+    let (columns, rows) = terminal::size()?;
+    let (columns, rows) = (columns as usize, rows as usize);
+    let list_height = rows.saturating_sub(1);
+
+    // only show a preview when there is room for it
+    let list_width = if columns >= 80 { columns / 2 } else { columns };
+    let preview_width = columns.saturating_sub(list_width + 3);
+
+    // keep the selected line in view
+    if state.selected < state.scroll {
+        state.scroll = state.selected;
+    } else if state.selected >= state.scroll + list_height {
+        state.scroll = state.selected + 1 - list_height;
+    }
+
+    queue!(stderr, Clear(ClearType::All), MoveTo(0, 0))?;
+    let mut header = format!(
+        "{} {}  {}/{}",
+        prompt,
+        state.query,
+        state.matches.len(),
+        candidates.len()
+    );
+    if state.multi {
+        header.push_str(&format!("  ({} marked, tab to mark)", state.marked.len()));
+    }
+    queue!(stderr, Print(style::truncate(&header, columns)))?;
+
+    for row in 0..list_height {
+        let index = state.scroll + row;
+        let candidate = match state.matches.get(index) {
+            Some(i) => &candidates[*i],
+            None => break,
+        };
+        let cursor = if index == state.selected { ">" } else { " " };
+        let mark = if state.marked.contains(&state.matches[index]) {
+            "*"
+        } else {
+            " "
+        };
+        let line = format!("{}{}{}", cursor, mark, candidate.label);
+        queue!(
+            stderr,
+            MoveTo(0, (row + 1) as u16),
+            Print(style::truncate(&line, list_width))
+        )?;
+    }
+
+    if preview_width > 0 {
+        let lines = match state.matches.get(state.selected) {
+            Some(i) => preview(&candidates[*i].path, list_height),
+            None => vec![],
+        };
+        for row in 0..list_height {
+            queue!(
+                stderr,
+                MoveTo((list_width + 1) as u16, (row + 1) as u16),
+                Print("│ ")
+            )?;
+            if let Some(line) = lines.get(row) {
+                queue!(stderr, Print(style::truncate(line, preview_width)))?;
+            }
+        }
+    }
+
+    stderr.flush()
+}
+
+/// the first lines of a text file, or the entries of a directory
+pub fn preview(path: &Path, max_lines: usize) -> Vec<String> {
+    if path.is_dir() {
+        let mut entries: Vec<String> = match fs::read_dir(path) {
+            Ok(entries) => entries
+                .flatten()
+                .map(|x| {
+                    let name = x.file_name().to_string_lossy().into_owned();
+                    if x.path().is_dir() {
+                        format!("{}/", name)
+                    } else {
+                        name
+                    }
+                })
+                .collect(),
+            Err(e) => return vec![format!("could not read directory, {}", e)],
+        };
+        entries.sort();
+        entries.truncate(max_lines);
+        return entries;
+    }
+
+    let mut buffer = vec![];
+    let read = fs::File::open(path).and_then(|x| x.take(16 * 1024).read_to_end(&mut buffer));
+    if let Err(e) = read {
+        return vec![format!("could not read file, {}", e)];
+    }
+    if buffer.contains(&0) {
+        let size = fs::metadata(path).map(|x| x.len()).unwrap_or(0);
+        return vec![format!("binary file, {} bytes", size)];
+    }
+
+    String::from_utf8_lossy(&buffer)
+        .lines()
+        .take(max_lines)
+        // tabs and control characters would mess up the layout
+        .map(|x| {
+            x.replace('\t', "    ")
+                .replace(|c: char| c.is_control(), "")
+        })
+        .collect()
+}
@@ -1,13 +1,162 @@
-use inquire::Select;
+use serde::{Deserialize, Serialize};
+use std::io::{self, IsTerminal, Write};
+use std::process::{Command, Stdio};
 
-use crate::tags::types::StoredFile;
+use super::picker::{self, Candidate};
+use super::style;
+use crate::error::Error;
+use crate::tags::types::{StoredFile, Tag};
 
-pub fn choose_file(options: Vec<StoredFile>) -> Result<StoredFile, String> {
-    let answer = Select::<StoredFile>::new("Chooses file", options).prompt();
+/// which fuzzy finder is used to choose between files
+#[derive(Deserialize, Serialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Picker {
+    #[default]
+    Builtin,
+    Fzf,
+    Skim,
+}
+impl Picker {
+    pub const ALL: [Picker; 3] = [Picker::Builtin, Picker::Fzf, Picker::Skim];
 
-    if let Ok(x) = answer {
-        return Ok(x);
-    } else {
-        return Err("No answer chosen".to_owned());
+    pub fn name(&self) -> &'static str {
+        match self {
+            Picker::Builtin => "builtin",
+            Picker::Fzf => "fzf",
+            Picker::Skim => "skim",
+        }
     }
 }
+
+/// `hint` says how to avoid the question, it is shown when there is no terminal to ask it
+pub fn choose_file(
+    options: Vec<(StoredFile, Vec<Tag>)>,
+    picker: Picker,
+    hint: &str,
+) -> Result<StoredFile, Error> {
+    Ok(choose(options, picker, false, hint)?.remove(0))
+}
+
+/// lets the user mark any amount of files
+pub fn choose_files(
+    options: Vec<(StoredFile, Vec<Tag>)>,
+    picker: Picker,
+    hint: &str,
+) -> Result<Vec<StoredFile>, Error> {
+    choose(options, picker, true, hint)
+}
+
+fn choose(
+    mut options: Vec<(StoredFile, Vec<Tag>)>,
+    picker: Picker,
+    multi: bool,
+    hint: &str,
+) -> Result<Vec<StoredFile>, Error> {
+    // one file is the answer already, the finder is only shown when there is something to choose
+    if options.len() == 1 && !multi {
+        return Ok(vec![options.remove(0).0]);
+    }
+
+    // most used files first
+    let now = crate::now();
+    options.sort_by(|a, b| b.0.frecency(now).total_cmp(&a.0.frecency(now)));
+
+    let chosen = match picker {
+        Picker::Builtin => {
+            if !io::stderr().is_terminal() {
+                return Err(Error::Ambiguous(format!(
+                    "multiple files match and there is no terminal to ask which one, {}",
+                    hint
+                )));
+            }
+            let candidates = candidates(&options, style::stderr_color());
+            picker::pick("Choose file >", &candidates, multi)?
+        }
+        Picker::Fzf => external("fzf", &options, multi)?,
+        Picker::Skim => external("sk", &options, multi)?,
+    };
+
+    match chosen {
+        Some(indexes) if !indexes.is_empty() => {
+            Ok(indexes.into_iter().map(|i| options[i].0.clone()).collect())
+        }
+        _ => Err(Error::Cancelled("No answer chosen".to_owned())),
+    }
+}
+
+fn candidates(options: &[(StoredFile, Vec<Tag>)], color: bool) -> Vec<Candidate> {
+    options
+        .iter()
+        .map(|(file, tags)| Candidate {
+            label: format!(
+                "{} {} {}",
+                file.name,
+                style::dimmed(&style::short_path(&file.path, 40), color),
+                style::tags(tags, color)
+            ),
+            search: format!(
+                "{} {} {}",
+                file.name,
+                file.path.to_string_lossy(),
+                tags.iter()
+                    .map(|x| x.name.clone())
+                    .collect::<Vec<String>>()
+                    .join(" ")
+            ),
+            path: file.path.clone(),
+        })
+        .collect()
+}
+
+/// lets fzf or skim choose, the preview is made by the hidden `preview` subcommand
+fn external(
+    program: &str,
+    options: &[(StoredFile, Vec<Tag>)],
+    multi: bool,
+) -> Result<Option<Vec<usize>>, Error> {
+    let binary = match std::env::current_exe() {
+        Ok(path) => path.to_string_lossy().into_owned(),
+        Err(e) => return Err(e.into()),
+    };
+
+    let child = Command::new(program)
+        .args(if multi { vec!["--multi"] } else { vec![] })
+        .args([
+            "--ansi",
+            "--delimiter",
+            "\t",
+            "--with-nth",
+            "3..",
+            "--prompt",
+            "Choose file > ",
+            "--preview",
+            &format!("{} preview {{2}}", shell_words::quote(&binary)),
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => return Err(Error::Other(format!("could not start {}, {}", program, e))),
+    };
+
+    // the first two fields are hidden, they are used to find the chosen file and for the preview
+    // the path is written as it is, so the preview also works for paths that are not valid utf-8
+    let mut lines: Vec<u8> = vec![];
+    for (i, x) in candidates(options, true).iter().enumerate() {
+        lines.extend_from_slice(format!("{}\t", i).as_bytes());
+        lines.extend_from_slice(x.path.as_os_str().as_encoded_bytes());
+        lines.extend_from_slice(format!("\t{}\n", x.label.replace('\t', " ")).as_bytes());
+    }
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(&lines)?;
+    }
+
+    let output = child.wait_with_output()?;
+    let chosen: Vec<usize> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|x| x.split('\t').next())
+        .filter_map(|x| x.trim().parse::<usize>().ok())
+        .collect();
+    Ok(Some(chosen))
+}
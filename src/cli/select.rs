@@ -0,0 +1,81 @@
+use std::ffi::OsStr;
+use std::path::Path;
+
+use super::prompt::{self, Picker};
+use super::style;
+use crate::error::Error;
+use crate::tags::db::Database;
+use crate::tags::types::StoredFile;
+
+/// the stored files meant by a path or a name
+///
+/// a path on disk wins over a name, the literal text is also compared to stored paths for files that have gone missing
+pub fn matching(database: &Database, file: &OsStr) -> Vec<StoredFile> {
+    let stored = database.list_files();
+    let canonical = Path::new(file).canonicalize().ok();
+
+    let by_path: Vec<StoredFile> = stored
+        .iter()
+        .filter(|x| x.path.as_os_str() == file || canonical.as_ref().is_some_and(|c| &x.path == c))
+        .cloned()
+        .collect();
+    if !by_path.is_empty() {
+        return by_path;
+    }
+
+    stored
+        .into_iter()
+        .filter(|x| OsStr::new(&x.name) == file)
+        .collect()
+}
+
+/// the one stored file meant by a path or name, asks the user which one if several files match
+pub fn resolve(database: &Database, picker: Picker, file: &OsStr) -> Result<StoredFile, Error> {
+    let matches = matching(database, file);
+    if matches.is_empty() {
+        return Err(Error::NotFound(format!(
+            "no tracked file with the path or name '{}'",
+            style::escape_path(Path::new(file))
+        )));
+    }
+    ask(database, picker, matches)
+}
+
+/// lets the user choose one of these files, with their tags shown
+pub fn ask(
+    database: &Database,
+    picker: Picker,
+    files: Vec<StoredFile>,
+) -> Result<StoredFile, Error> {
+    prompt::choose_file(
+        with_tags(database, files),
+        picker,
+        "pass a more specific path",
+    )
+}
+
+/// lets the user mark any amount of these files
+pub fn ask_many(
+    database: &Database,
+    picker: Picker,
+    files: Vec<StoredFile>,
+) -> Result<Vec<StoredFile>, Error> {
+    prompt::choose_files(
+        with_tags(database, files),
+        picker,
+        "pass more specific tags",
+    )
+}
+
+fn with_tags(
+    database: &Database,
+    files: Vec<StoredFile>,
+) -> Vec<(StoredFile, Vec<crate::tags::types::Tag>)> {
+    files
+        .into_iter()
+        .map(|x| {
+            let tags = database.get_file_tags(&x.name);
+            (x, tags)
+        })
+        .collect()
+}
@@ -0,0 +1,218 @@
+use clap::ValueEnum;
+use clap_complete::{generate, shells};
+use std::path::Path;
+
+use super::parse;
+use crate::error::Error;
+
+#[derive(ValueEnum, Clone, Copy)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Nushell,
+}
+
+/// subcommands where the positional arguments are (mostly) tag names, these get tag completion
+const TAG_SUBCOMMANDS: [&str; 8] = [
+    "getfile",
+    "open",
+    "edit",
+    "listfiles",
+    "settags",
+    "removetag",
+    "edittag",
+    "get-as-link-directory",
+];
+
+/// the script that `init` prints, `cmd` is the name of the shell function
+pub fn init(shell: Shell, cmd: &str) -> Result<String, Error> {
+    let binary = match std::env::current_exe() {
+        Ok(path) => path,
+        Err(e) => {
+            return Err(Error::Io(format!(
+                "could not find the path of this executable, {}",
+                e
+            )))
+        }
+    };
+    let binary = quote(shell, &binary);
+
+    let script = match shell {
+        Shell::Bash => format!("{}\n{}", POSIX_FUNCTION, bash_completions(cmd)?),
+        Shell::Zsh => format!("{}\n{}", POSIX_FUNCTION, zsh_completions(cmd)?),
+        Shell::Fish => format!("{}\n{}", FISH_FUNCTION, fish_completions(cmd)?),
+        Shell::Nushell => format!("{}\n{}", nushell_completions(), NUSHELL_FUNCTION),
+    };
+
+    Ok(script
+        .replace("__CMD__", cmd)
+        .replace("__BIN__", &binary)
+        .replace("__TAG_SUBCOMMANDS__", &TAG_SUBCOMMANDS.join("|")))
+}
+
+/// the path as a quoted string literal for the given shell
+fn quote(shell: Shell, path: &Path) -> String {
+    let path = path.to_string_lossy();
+    match shell {
+        Shell::Bash | Shell::Zsh => format!("'{}'", path.replace('\'', "'\\''")),
+        Shell::Fish => format!("'{}'", path.replace('\\', "\\\\").replace('\'', "\\'")),
+        Shell::Nushell => format!("\"{}\"", path.replace('\\', "\\\\").replace('"', "\\\"")),
+    }
+}
+
+fn clap_completions<G: clap_complete::Generator>(generator: G, cmd: &str) -> Result<String, Error> {
+    let mut buffer: Vec<u8> = vec![];
+    generate(generator, &mut parse::command(), cmd, &mut buffer);
+    String::from_utf8(buffer).map_err(|e| Error::Other(e.to_string()))
+}
+
+fn bash_completions(cmd: &str) -> Result<String, Error> {
+    let generated = clap_completions(shells::Bash, cmd)?;
+    Ok(format!(
+        "{}\n{}",
+        generated,
+        r#"___CMD___with_tags() {
+    ___CMD__ "$@"
+    local cur="${COMP_WORDS[COMP_CWORD]}"
+    case "${COMP_WORDS[1]}" in
+        __TAG_SUBCOMMANDS__)
+            if [[ "$cur" != -* ]]; then
+                local prefix="${cur%%[!+=]*}"
+                COMPREPLY+=( $(compgen -P "$prefix" -W "$(command __BIN__ listtags --template '{name}' 2>/dev/null)" -- "${cur#"$prefix"}") )
+            fi
+            ;;
+    esac
+}
+complete -F ___CMD___with_tags -o bashdefault -o default __CMD__
+"#
+    ))
+}
+
+fn zsh_completions(cmd: &str) -> Result<String, Error> {
+    let generated = clap_completions(shells::Zsh, cmd)?;
+    Ok(format!(
+        "{}\n{}",
+        generated,
+        r#"___CMD___with_tags() {
+    ___CMD__ "$@"
+    case "$words[2]" in
+        __TAG_SUBCOMMANDS__)
+            if [[ "$PREFIX" != -* ]]; then
+                local -a tags
+                tags=(${(f)"$(command __BIN__ listtags --template '{name}' 2>/dev/null)"})
+                compset -P '[+=]#'
+                compadd -a tags
+            fi
+            ;;
+    esac
+}
+compdef ___CMD___with_tags __CMD__
+"#
+    ))
+}
+
+fn fish_completions(cmd: &str) -> Result<String, Error> {
+    let generated = clap_completions(shells::Fish, cmd)?;
+    Ok(format!(
+        "{}\n{}",
+        generated,
+        r#"complete -c __CMD__ -n "__fish_seen_subcommand_from __TAG_SUBCOMMANDS__" -f -a "(command __BIN__ listtags --template '{name}' 2>/dev/null)"
+"#
+        .replace(
+            "__TAG_SUBCOMMANDS__",
+            &TAG_SUBCOMMANDS.join(" ")
+        )
+    ))
+}
+
+/// nushell has no completion generator in clap_complete, so the subcommands and flags are written out as a record
+fn nushell_completions() -> String {
+    let command = parse::command();
+    let mut entries: Vec<String> = vec![];
+    for subcommand in command.get_subcommands().filter(|x| !x.is_hide_set()) {
+        let flags: Vec<String> = subcommand
+            .get_arguments()
+            .filter_map(|x| x.get_long())
+            .map(|x| format!("\"--{}\"", x))
+            .collect();
+        entries.push(format!(
+            "        \"{}\": [{}]",
+            subcommand.get_name(),
+            flags.join(" ")
+        ));
+    }
+
+    format!(
+        "{}{}{}",
+        r#"def "nu-complete __CMD__" [context: string] {
+    let subcommands = {
+"#,
+        entries.join("\n"),
+        r#"
+    }
+    let words = ($context | str trim --left | split row --regex '\s+')
+    if ($words | length) <= 2 {
+        return ($subcommands | columns | append ["cd"])
+    }
+    let subcommand = ($words | get 1)
+    let current = ($words | last)
+    if ($current | str starts-with "-") {
+        return ($subcommands | get --ignore-errors $subcommand | default [])
+    }
+    if $subcommand in [__TAG_LIST__] {
+        let prefix = ($current | parse --regex '^(?<prefix>[+=]*)' | get 0.prefix)
+        return (^__BIN__ listtags --template '{name}' | lines | each {|tag| $prefix + $tag })
+    }
+    return []
+}
+"#
+        .replace(
+            "__TAG_LIST__",
+            &TAG_SUBCOMMANDS
+                .iter()
+                .map(|x| format!("\"{}\"", x))
+                .collect::<Vec<String>>()
+                .join(" "),
+        )
+    )
+}
+
+const POSIX_FUNCTION: &str = r#"__CMD__() {
+    local target
+    case "$1" in
+        cd)
+            target="$(command __BIN__ getfile "${@:2}")" || return
+            [ -d "$target" ] || target="$(dirname -- "$target")"
+            cd -- "$target"
+            ;;
+        *)
+            command __BIN__ "$@"
+            ;;
+    esac
+}
+"#;
+
+const FISH_FUNCTION: &str = r#"function __CMD__
+    switch "$argv[1]"
+        case cd
+            set -l target (command __BIN__ getfile $argv[2..-1]); or return
+            test -d "$target"; or set target (dirname -- "$target")
+            cd $target
+        case '*'
+            command __BIN__ $argv
+    end
+end
+"#;
+
+const NUSHELL_FUNCTION: &str = r#"def --env --wrapped __CMD__ [...args: string@"nu-complete __CMD__"] {
+    let subcommand = ($args | get --ignore-errors 0 | default "")
+    let rest = ($args | skip 1)
+    if $subcommand == "cd" {
+        let target = (^__BIN__ getfile ...$rest | str trim)
+        if ($target | path type) == "dir" { cd $target } else { cd ($target | path dirname) }
+    } else {
+        ^__BIN__ ...$args
+    }
+}
+"#;
@@ -0,0 +1,134 @@
+use std::io::{self, IsTerminal};
+use std::path::Path;
+
+use crate::tags::types::{self, Tag};
+
+/// colours are only used when writing to a terminal, and never when NO_COLOR is set
+fn color_allowed(is_terminal: bool) -> bool {
+    is_terminal && std::env::var_os("NO_COLOR").is_none()
+}
+
+pub fn stdout_color() -> bool {
+    color_allowed(io::stdout().is_terminal())
+}
+
+pub fn stderr_color() -> bool {
+    color_allowed(io::stderr().is_terminal())
+}
+
+/// the tag name with its icon, in its colour if `color` is true
+pub fn tag(tag: &Tag, color: bool) -> String {
+    let text = match &tag.icon {
+        Some(icon) => format!("{} {}", icon, tag.name),
+        None => tag.name.clone(),
+    };
+
+    match (&tag.color, color) {
+        (Some(tag_color), true) => format!("\x1b[{}m{}\x1b[0m", tag_color.ansi_code(), text),
+        _ => text,
+    }
+}
+
+pub fn tags(tags: &[Tag], color: bool) -> String {
+    tags.iter()
+        .map(|x| self::tag(x, color))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// cuts text off at `width` visible characters, colour codes are kept and dont count towards the width
+pub fn truncate(text: &str, width: usize) -> String {
+    let mut result = String::new();
+    let mut visible = 0;
+    let mut in_escape = false;
+    let mut has_escape = false;
+
+    for c in text.chars() {
+        if in_escape {
+            in_escape = c != 'm';
+        } else if c == '\x1b' {
+            in_escape = true;
+            has_escape = true;
+        } else if visible == width {
+            break;
+        } else {
+            visible += 1;
+        }
+        result.push(c);
+    }
+
+    if has_escape {
+        result.push_str("\x1b[0m");
+    }
+    result
+}
+
+/// the path as text, bytes that are not valid utf-8 are written as `\xff`
+pub fn escape_path(path: &Path) -> String {
+    types::escape_os_str(path.as_os_str())
+}
+
+/// the path with the home directory replaced by `~`, and only the last directories if it is long
+pub fn short_path(path: &Path, max_width: usize) -> String {
+    let mut path = escape_path(path);
+    if let Some(home) = dirs::home_dir() {
+        let home = home.to_string_lossy().into_owned();
+        if let Some(rest) = path.strip_prefix(&home) {
+            path = format!("~{}", rest);
+        }
+    }
+
+    let parts: Vec<&str> = path.split('/').collect();
+    let mut result = path.clone();
+    let mut skip = 1;
+    while result.chars().count() > max_width && skip < parts.len() - 1 {
+        result = format!("…/{}", parts[skip + 1..].join("/"));
+        skip += 1;
+    }
+    result
+}
+
+pub fn dimmed(text: &str, color: bool) -> String {
+    if color {
+        format!("\x1b[2m{}\x1b[0m", text)
+    } else {
+        text.to_owned()
+    }
+}
+
+/// formats an epoch as `YYYY-MM-DD HH:MM` in utc, 0 means the file was never used
+pub fn time(epoch: u64) -> String {
+    if epoch == 0 {
+        return "never".to_owned();
+    }
+
+    let (year, month, day) = date(epoch);
+    let seconds_of_day = epoch % 86400;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day % 3600 / 60
+    )
+}
+
+/// the year, month and day of an epoch in utc
+pub fn date(epoch: u64) -> (i64, i64, i64) {
+    let days = (epoch / 86400) as i64;
+
+    // civil from days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
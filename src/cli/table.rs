@@ -0,0 +1,60 @@
+use std::io::{self, Write};
+
+use crate::error::Error;
+
+/// prints rows as left aligned columns, the widths ignore ansi colour codes
+pub fn print(header: &[&str], rows: &[Vec<String>], show_header: bool) -> Result<(), Error> {
+    let column_count = rows.iter().map(|x| x.len()).max().unwrap_or(0);
+    let mut widths: Vec<usize> = vec![0; column_count.max(header.len())];
+    for (i, name) in header.iter().enumerate() {
+        widths[i] = name.chars().count();
+    }
+    for row in rows {
+        for (i, cell) in row.iter().enumerate() {
+            widths[i] = widths[i].max(visible_width(cell));
+        }
+    }
+
+    let header: Option<Vec<String>> =
+        show_header.then(|| header.iter().map(|x| x.to_uppercase()).collect());
+    let mut stdout = io::stdout().lock();
+    let result = header
+        .iter()
+        .chain(rows)
+        .try_for_each(|row| print_row(&mut stdout, row, &widths));
+
+    match result {
+        // the reader stopped early, like `head` does
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        Err(e) => Err(e.into()),
+        Ok(()) => Ok(()),
+    }
+}
+
+fn print_row(stdout: &mut impl Write, row: &[String], widths: &[usize]) -> io::Result<()> {
+    let mut line = String::new();
+    for (i, cell) in row.iter().enumerate() {
+        line.push_str(cell);
+        // the last column does not need padding
+        if i + 1 < row.len() {
+            let padding = widths[i] - visible_width(cell) + 2;
+            line.push_str(&" ".repeat(padding));
+        }
+    }
+    writeln!(stdout, "{}", line.trim_end())
+}
+
+fn visible_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut in_escape = false;
+    for c in text.chars() {
+        if in_escape {
+            in_escape = c != 'm';
+        } else if c == '\x1b' {
+            in_escape = true;
+        } else {
+            width += 1;
+        }
+    }
+    width
+}
@@ -0,0 +1,470 @@
+use crossterm::cursor::MoveTo;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::queue;
+use crossterm::style::Print;
+use crossterm::terminal::{self, Clear, ClearType};
+use std::io::{self, Stderr, Write};
+use std::path::PathBuf;
+
+use super::picker::TerminalGuard;
+use super::style;
+use crate::error::Error;
+use crate::tags::db::{Database, Mirror};
+use crate::tags::tag_relations;
+use crate::tags::types::{StoredFile, Tag};
+
+#[derive(PartialEq)]
+enum Focus {
+    Files,
+    Tags,
+}
+
+/// what the bottom line is currently used for
+enum Input {
+    None,
+    Filter,
+    NewTag(String),
+}
+
+/// a change that can be undone
+enum Change {
+    /// a tag was toggled, with the paths of the files that had the tag before
+    Toggle {
+        tag: String,
+        files: Vec<PathBuf>,
+        had_tag: Vec<PathBuf>,
+    },
+    CreateTag(String),
+}
+
+/// a line in the tag tree, child tags are shown below their parent but cant be toggled
+struct TagLine {
+    tag: String,
+    child: Option<String>,
+}
+
+struct State {
+    database_file: PathBuf,
+    mirror: Mirror,
+    files: Vec<StoredFile>,
+    file_tags: Vec<Vec<Tag>>,
+    tags: Vec<Tag>,
+    tag_lines: Vec<TagLine>,
+    query: String,
+    focus: Focus,
+    input: Input,
+    file_cursor: usize,
+    tag_cursor: usize,
+    /// paths of marked files
+    marked: Vec<PathBuf>,
+    history: Vec<Change>,
+    message: String,
+}
+impl State {
+    fn database(&self) -> Result<Database, Error> {
+        Ok(Database::open(self.database_file.clone())?.with_mirror(self.mirror))
+    }
+
+    fn reload(&mut self) -> Result<(), Error> {
+        let database = self.database()?;
+        let filter = match tag_relations::parse_tags(
+            &self
+                .query
+                .split_whitespace()
+                .map(|x| x.to_owned())
+                .collect::<Vec<String>>(),
+        ) {
+            Ok(filter) => filter,
+            Err(e) => {
+                self.message = e.to_string();
+                tag_relations::parse_tags(&[])?
+            }
+        };
+
+        self.files = database.get_files(filter)?;
+        self.file_tags = self
+            .files
+            .iter()
+            .map(|x| database.get_file_tags(&x.name))
+            .collect();
+        self.tags = database.list_tags()?;
+        self.tag_lines = self
+            .tags
+            .iter()
+            .flat_map(|tag| {
+                let mut lines = vec![TagLine {
+                    tag: tag.name.clone(),
+                    child: None,
+                }];
+                lines.extend(tag.children.iter().map(|child| TagLine {
+                    tag: tag.name.clone(),
+                    child: Some(child.name.clone()),
+                }));
+                lines
+            })
+            .collect();
+
+        self.file_cursor = self.file_cursor.min(self.files.len().saturating_sub(1));
+        self.tag_cursor = self.tag_cursor.min(self.tag_lines.len().saturating_sub(1));
+        Ok(())
+    }
+
+    /// the marked files, or the file under the cursor if none are marked
+    fn selection(&self) -> Vec<PathBuf> {
+        if !self.marked.is_empty() {
+            return self
+                .files
+                .iter()
+                .filter(|x| self.marked.contains(&x.path))
+                .map(|x| x.path.clone())
+                .collect();
+        }
+        self.files
+            .get(self.file_cursor)
+            .map(|x| vec![x.path.clone()])
+            .unwrap_or_default()
+    }
+
+    /// the paths of the given files that have the tag
+    fn having_tag(&self, files: &[PathBuf], tag: &str) -> Vec<PathBuf> {
+        self.files
+            .iter()
+            .zip(self.file_tags.iter())
+            .filter(|(file, tags)| files.contains(&file.path) && tags.iter().any(|x| x.name == tag))
+            .map(|(file, _)| file.path.clone())
+            .collect()
+    }
+
+    fn toggle(&mut self) -> Result<(), Error> {
+        let line = match self.tag_lines.get(self.tag_cursor) {
+            Some(line) => line,
+            None => return Ok(()),
+        };
+        if line.child.is_some() {
+            self.message = "child tags cant be set on files yet".to_owned();
+            return Ok(());
+        }
+        let tag = line.tag.clone();
+        let files = self.selection();
+        if files.is_empty() {
+            return Ok(());
+        }
+
+        // when every file has the tag it is removed, otherwise it is added to all of them
+        let had_tag = self.having_tag(&files, &tag);
+        let remove = had_tag.len() == files.len();
+        self.set_tag(&files, &tag, !remove)?;
+
+        self.message = format!(
+            "{} '{}' {} {} file(s)",
+            if remove { "removed" } else { "added" },
+            tag,
+            if remove { "from" } else { "to" },
+            files.len()
+        );
+        self.history.push(Change::Toggle {
+            tag,
+            files,
+            had_tag,
+        });
+        self.reload()
+    }
+
+    fn undo(&mut self) -> Result<(), Error> {
+        match self.history.pop() {
+            None => self.message = "nothing to undo".to_owned(),
+            Some(Change::Toggle {
+                tag,
+                files,
+                had_tag,
+            }) => {
+                // either every file had the tag and it was removed, or it was added to the ones without it
+                let had_not: Vec<PathBuf> =
+                    files.into_iter().filter(|x| !had_tag.contains(x)).collect();
+                if had_not.is_empty() {
+                    self.set_tag(&had_tag, &tag, true)?;
+                } else {
+                    self.set_tag(&had_not, &tag, false)?;
+                }
+                self.message = format!("undid change to '{}'", tag);
+            }
+            Some(Change::CreateTag(name)) => {
+                self.database()?.delete_tags(vec![name.clone()])?;
+                self.message = format!("undid creating '{}'", name);
+            }
+        }
+        self.reload()
+    }
+
+    fn create_tag(&mut self, name: String) -> Result<(), Error> {
+        if name.is_empty() {
+            return Ok(());
+        }
+        if let Err(e) = tag_relations::validate_tag_name(&name) {
+            self.message = e.to_string();
+            return Ok(());
+        }
+        if self.tags.iter().any(|x| x.name == name) {
+            self.message = format!("'{}' already exists", name);
+            return Ok(());
+        }
+        self.database()?.add_tags(vec![name.clone()])?;
+        self.message = format!("created '{}'", name);
+        self.history.push(Change::CreateTag(name));
+        self.reload()
+    }
+
+    /// adds the tag to all the files or removes it from them, in one change
+    fn set_tag(&self, files: &[PathBuf], tag: &str, add: bool) -> Result<(), Error> {
+        let prefix = if add { "+" } else { "=" };
+        let filter = tag_relations::parse_tags(&[format!("{}{}", prefix, tag)])?;
+        self.database()?.set_tags(files, filter)
+    }
+}
+
+/// a full screen view to tag many files at once, changes are written right away
+pub fn run(database_file: PathBuf, mirror: Mirror) -> Result<(), Error> {
+    let mut state = State {
+        database_file,
+        mirror,
+        files: vec![],
+        file_tags: vec![],
+        tags: vec![],
+        tag_lines: vec![],
+        query: String::new(),
+        focus: Focus::Files,
+        input: Input::None,
+        file_cursor: 0,
+        tag_cursor: 0,
+        marked: vec![],
+        history: vec![],
+        message: String::new(),
+    };
+    state.reload()?;
+
+    let mut guard = TerminalGuard::new()?;
+    loop {
+        draw(&mut guard.stderr, &state)?;
+
+        let key = match event::read()? {
+            Event::Key(key) => key,
+            _ => continue,
+        };
+        state.message.clear();
+
+        let result = match state.input {
+            Input::Filter => filter_key(&mut state, key),
+            Input::NewTag(_) => new_tag_key(&mut state, key),
+            Input::None => {
+                if is_quit(&key) {
+                    return Ok(());
+                }
+                normal_key(&mut state, key)
+            }
+        };
+        if let Err(e) = result {
+            state.message = e.to_string();
+        }
+    }
+}
+
+fn is_quit(key: &KeyEvent) -> bool {
+    key.code == KeyCode::Char('q')
+        || (key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL))
+}
+
+fn normal_key(state: &mut State, key: KeyEvent) -> Result<(), Error> {
+    match key.code {
+        KeyCode::Tab | KeyCode::Left | KeyCode::Right | KeyCode::Char('h') | KeyCode::Char('l') => {
+            state.focus = match state.focus {
+                Focus::Files => Focus::Tags,
+                Focus::Tags => Focus::Files,
+            }
+        }
+        KeyCode::Up | KeyCode::Char('k') => match state.focus {
+            Focus::Files => state.file_cursor = state.file_cursor.saturating_sub(1),
+            Focus::Tags => state.tag_cursor = state.tag_cursor.saturating_sub(1),
+        },
+        KeyCode::Down | KeyCode::Char('j') => match state.focus {
+            Focus::Files => {
+                state.file_cursor = (state.file_cursor + 1).min(state.files.len().saturating_sub(1))
+            }
+            Focus::Tags => {
+                state.tag_cursor =
+                    (state.tag_cursor + 1).min(state.tag_lines.len().saturating_sub(1))
+            }
+        },
+        KeyCode::Char(' ') | KeyCode::Enter => match state.focus {
+            Focus::Files => {
+                if let Some(file) = state.files.get(state.file_cursor) {
+                    let path = file.path.clone();
+                    match state.marked.iter().position(|x| *x == path) {
+                        Some(i) => _ = state.marked.remove(i),
+                        None => state.marked.push(path),
+                    }
+                    state.file_cursor =
+                        (state.file_cursor + 1).min(state.files.len().saturating_sub(1));
+                }
+            }
+            Focus::Tags => state.toggle()?,
+        },
+        KeyCode::Char('a') => {
+            if state.marked.len() == state.files.len() {
+                state.marked.clear();
+            } else {
+                state.marked = state.files.iter().map(|x| x.path.clone()).collect();
+            }
+        }
+        KeyCode::Char('/') => state.input = Input::Filter,
+        KeyCode::Char('n') => state.input = Input::NewTag(String::new()),
+        KeyCode::Char('u') => state.undo()?,
+        _ => {}
+    }
+    Ok(())
+}
+
+fn filter_key(state: &mut State, key: KeyEvent) -> Result<(), Error> {
+    match key.code {
+        KeyCode::Enter | KeyCode::Esc => state.input = Input::None,
+        KeyCode::Backspace => {
+            state.query.pop();
+            state.reload()?;
+        }
+        KeyCode::Char(c) => {
+            state.query.push(c);
+            state.reload()?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn new_tag_key(state: &mut State, key: KeyEvent) -> Result<(), Error> {
+    let name = match &mut state.input {
+        Input::NewTag(name) => name,
+        _ => return Ok(()),
+    };
+    match key.code {
+        KeyCode::Esc => state.input = Input::None,
+        KeyCode::Enter => {
+            let name = name.clone();
+            state.input = Input::None;
+            state.create_tag(name)?;
+        }
+        KeyCode::Backspace => _ = name.pop(),
+        KeyCode::Char(c) => name.push(c),
+        _ => {}
+    }
+    Ok(())
+}
+
+fn draw(stderr: &mut Stderr, state: &State) -> io::Result<()> {
+    let (columns, rows) = terminal::size()?;
+    let (columns, rows) = (columns as usize, rows as usize);
+    let list_height = rows.saturating_sub(3);
+    let file_width = columns * 3 / 5;
+    let tag_width = columns.saturating_sub(file_width + 2);
+    let color = style::stderr_color();
+
+    queue!(stderr, Clear(ClearType::All), MoveTo(0, 0))?;
+    let header = "tab switch  space mark/toggle  a mark all  / filter  n new tag  u undo  q quit";
+    queue!(
+        stderr,
+        Print(style::truncate(&style::dimmed(header, color), columns))
+    )?;
+
+    let selection = state.selection();
+    let file_scroll = state
+        .file_cursor
+        .saturating_sub(list_height.saturating_sub(1));
+    for row in 0..list_height {
+        let index = file_scroll + row;
+        let (file, tags) = match (state.files.get(index), state.file_tags.get(index)) {
+            (Some(file), Some(tags)) => (file, tags),
+            _ => break,
+        };
+        let cursor = if state.focus == Focus::Files && index == state.file_cursor {
+            ">"
+        } else {
+            " "
+        };
+        let mark = if state.marked.contains(&file.path) {
+            "*"
+        } else {
+            " "
+        };
+        let line = format!(
+            "{}{}{} {}",
+            cursor,
+            mark,
+            file.name,
+            style::tags(tags, color)
+        );
+        queue!(
+            stderr,
+            MoveTo(0, (row + 1) as u16),
+            Print(style::truncate(&line, file_width))
+        )?;
+    }
+
+    let tag_scroll = state
+        .tag_cursor
+        .saturating_sub(list_height.saturating_sub(1));
+    for row in 0..list_height {
+        queue!(
+            stderr,
+            MoveTo(file_width as u16, (row + 1) as u16),
+            Print("│ ")
+        )?;
+        let index = tag_scroll + row;
+        let line = match state.tag_lines.get(index) {
+            Some(line) => line,
+            None => continue,
+        };
+        let cursor = if state.focus == Focus::Tags && index == state.tag_cursor {
+            ">"
+        } else {
+            " "
+        };
+        let text = match &line.child {
+            Some(child) => format!("{}    {}", cursor, style::dimmed(child, color)),
+            None => {
+                let having = state.having_tag(&selection, &line.tag).len();
+                let check = if having == 0 {
+                    "[ ]"
+                } else if having == selection.len() {
+                    "[x]"
+                } else {
+                    "[~]"
+                };
+                let tag = state.tags.iter().find(|x| x.name == line.tag);
+                let name = tag.map_or(line.tag.clone(), |x| style::tag(x, color));
+                format!("{}{} {}", cursor, check, name)
+            }
+        };
+        queue!(stderr, Print(style::truncate(&text, tag_width)))?;
+    }
+
+    let bottom = match &state.input {
+        Input::Filter => format!("filter: {}", state.query),
+        Input::NewTag(name) => format!("new tag: {}", name),
+        Input::None if !state.message.is_empty() => state.message.clone(),
+        Input::None => format!(
+            "{} files, {} marked{}",
+            state.files.len(),
+            state.marked.len(),
+            if state.query.is_empty() {
+                String::new()
+            } else {
+                format!(", filter: {}", state.query)
+            }
+        ),
+    };
+    queue!(
+        stderr,
+        MoveTo(0, rows.saturating_sub(1) as u16),
+        Print(style::truncate(&bottom, columns))
+    )?;
+
+    stderr.flush()
+}
@@ -0,0 +1,92 @@
+use std::fmt::Display;
+use std::io;
+
+/// everything that can go wrong, each kind has its own exit code so scripts can react to it
+#[derive(Debug)]
+pub enum Error {
+    /// a file, tag or path that does not exist
+    NotFound(String),
+    /// several files match and there is no way to ask which one is meant
+    Ambiguous(String),
+    /// arguments that make no sense, like an invalid tag name or a tag that already exists
+    Invalid(String),
+    /// the database or another input could not be parsed
+    Parse(String),
+    /// reading or writing a file failed
+    Io(String),
+    /// another process is writing the database
+    Lock(String),
+    /// the config file is missing something or could not be parsed
+    Config(String),
+    /// the user cancelled a prompt
+    Cancelled(String),
+    /// anything else, like an opener that exited with an error
+    Other(String),
+}
+impl Error {
+    /// the exit code, 2 is left for usage errors which are reported by clap
+    pub fn code(&self) -> u8 {
+        match self {
+            Error::Other(_) => 1,
+            Error::NotFound(_) => 3,
+            Error::Ambiguous(_) => 4,
+            Error::Invalid(_) => 5,
+            Error::Parse(_) => 6,
+            Error::Io(_) => 7,
+            Error::Lock(_) => 8,
+            Error::Config(_) => 9,
+            Error::Cancelled(_) => 10,
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::NotFound(_) => "not_found",
+            Error::Ambiguous(_) => "ambiguous",
+            Error::Invalid(_) => "invalid",
+            Error::Parse(_) => "parse",
+            Error::Io(_) => "io",
+            Error::Lock(_) => "lock",
+            Error::Config(_) => "config",
+            Error::Cancelled(_) => "cancelled",
+            Error::Other(_) => "other",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            Error::NotFound(message)
+            | Error::Ambiguous(message)
+            | Error::Invalid(message)
+            | Error::Parse(message)
+            | Error::Io(message)
+            | Error::Lock(message)
+            | Error::Config(message)
+            | Error::Cancelled(message)
+            | Error::Other(message) => message,
+        }
+    }
+}
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message())
+    }
+}
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::NotFound => Error::NotFound(e.to_string()),
+            _ => Error::Io(e.to_string()),
+        }
+    }
+}
+impl From<inquire::InquireError> for Error {
+    fn from(e: inquire::InquireError) -> Self {
+        match e {
+            inquire::InquireError::OperationCanceled
+            | inquire::InquireError::OperationInterrupted => Error::Cancelled(e.to_string()),
+            inquire::InquireError::IO(e) => Error::from(e),
+            _ => Error::Other(e.to_string()),
+        }
+    }
+}
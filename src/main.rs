@@ -1,197 +1,2044 @@
+use inquire::{Confirm, Select, Text};
+use std::ffi::{OsStr, OsString};
 use std::fs;
-use std::io;
+use std::io::{self, BufRead, IsTerminal, Read, Write};
 use std::path::Path;
 use std::path::PathBuf;
 use std::process::ExitCode;
+use std::time::SystemTime;
 use symlink::{self, symlink_auto};
 use tags::db::Database;
+use tags::exchange::{Encoding, Export, Merge, Rewrite};
+use tags::journal::FileMove;
+use tags::migrate;
+use tags::sidecar;
 use tags::tag_relations;
+use tags::transfer;
+use tags::types::{os_string_from_bytes, AddMode, StoredFile, Tag, TagFilter};
+use tags::xdg_tags;
 
 mod cli;
+mod error;
+use crate::cli::output::{
+    ConfigRecord, FileRecord, HistoryRecord, InfoRecord, LibraryRecord, Output, TagRecord,
+};
 use crate::cli::parse::SubCommands;
 use crate::cli::*;
+use crate::error::Error;
 
 mod tags;
 
 pub fn main() -> ExitCode {
-    // create config if it doesnt exist
-    let config = match config::read() {
-        Ok(config) => config,
-        Err(e) => {
-            eprintln!("{}", e);
-            return ExitCode::FAILURE;
-        }
-    };
+    let cli = parse::parse();
+    let output = Output::new(cli.format, cli.template);
 
-    let result = match parse::parse() {
-        SubCommands::Listfiles {} => list_files(&config),
-        SubCommands::Listtags {} => list_tags(&config),
-        SubCommands::Getfile { tags, multiple } => get_file_path(&config, tags, multiple),
-        SubCommands::Addfile { file_path, option } => add_file(file_path, &config, option),
-        SubCommands::Addtag { names } => add_tag(names, &config),
-        SubCommands::Settags { tags, file } => set_tags(&config, tags, file),
-        SubCommands::Removefile { names } => remove_file(names, &config),
-        SubCommands::Removetag { names } => remove_tag(names, &config),
-        SubCommands::GetAsLinkDirectory { tags } => get_as_link_directory(&config, tags),
-        _ => Err("not yet implemented".to_owned()), // TODO
-    };
+    let result =
+        config::locate(cli.config, cli.library, cli.db).and_then(|location| match cli.command {
+            // works without a valid config, so the config can be fixed with it
+            SubCommands::Config { action } => config_command(&location, &output, action),
+            SubCommands::Libraries { action } => libraries_command(&location, &output, action),
+            SubCommands::Init { shell, cmd } => init(shell, cmd),
+            // create config if it doesnt exist
+            command => config::read(&location).and_then(|config| run(&config, &output, command)),
+        });
 
     if let Err(e) = result {
-        eprintln!("Something went wrong, {}", e);
-        return ExitCode::FAILURE;
+        output.error(&e);
+        ExitCode::from(e.code())
     } else {
-        return ExitCode::SUCCESS;
+        ExitCode::SUCCESS
     }
 }
 
-fn remove_tag(names: Vec<String>, config: &config::Config) -> Result<(), String> {
-    for name in names {
-        if let Err(e) = Database::open(config.clone().managed_directory)?.delete_tag(name) {
-            return Err(e.to_string());
+fn run(config: &config::Config, output: &Output, command: SubCommands) -> Result<(), Error> {
+    match command {
+        SubCommands::Listfiles {
+            query,
+            sort,
+            reverse,
+            columns,
+            no_header,
+            all_libraries,
+        } => list_files(
+            config,
+            output,
+            query,
+            sort,
+            reverse,
+            columns,
+            no_header,
+            all_libraries,
+        ),
+        SubCommands::Listtags {
+            query,
+            sort,
+            reverse,
+            columns,
+            no_header,
+        } => list_tags(config, output, query, sort, reverse, columns, no_header),
+        SubCommands::Getfile {
+            tags,
+            multiple,
+            all_libraries,
+        } => get_file_path(config, output, tags, multiple, all_libraries),
+        SubCommands::Open {
+            tags,
+            print_command,
+        } => open_file(config, tags, open::Action::Open, print_command),
+        SubCommands::Edit {
+            tags,
+            print_command,
+        } => open_file(config, tags, open::Action::Edit, print_command),
+        SubCommands::Select {
+            tags,
+            action,
+            change_tags,
+            strict,
+            create_tags,
+        } => select_files(
+            config,
+            output,
+            tags,
+            action,
+            change_tags,
+            parse::UnknownTags::from_flags(strict, create_tags),
+        ),
+        SubCommands::Tui => tui::run(config.clone().database_file, config.mirror),
+        SubCommands::Info { file } => info(config, output, file),
+        SubCommands::Addfile {
+            paths,
+            option,
+            on_conflict,
+            tags,
+            recursive,
+            include,
+            exclude,
+            stdin,
+            strict,
+            create_tags,
+        } => add_files(
+            config,
+            paths,
+            option,
+            on_conflict,
+            tags,
+            recursive,
+            include,
+            exclude,
+            stdin,
+            parse::UnknownTags::from_flags(strict, create_tags),
+        ),
+        SubCommands::Addtag { names } => add_tag(names, config),
+        SubCommands::Edittag {
+            name,
+            description,
+            color,
+            icon,
+        } => edit_tag(config, name, description, color, icon),
+        SubCommands::Settags {
+            tags,
+            file,
+            strict,
+            create_tags,
+        } => set_tags(
+            config,
+            tags,
+            file,
+            parse::UnknownTags::from_flags(strict, create_tags),
+        ),
+        SubCommands::Removefile { names } => remove_file(names, config),
+        SubCommands::Removetag { names } => remove_tag(names, config),
+        SubCommands::Undo => undo(config),
+        SubCommands::Redo => redo(config),
+        SubCommands::History { limit } => history(config, output, limit),
+        SubCommands::Dedupe { dry_run } => dedupe(config, dry_run),
+        SubCommands::Reorganize { dry_run } => reorganize(config, dry_run),
+        SubCommands::Check { roots, dry_run } => check(config, roots, dry_run),
+        SubCommands::SyncXattr {
+            paths,
+            prefer,
+            dry_run,
+        } => sync_xattr(config, paths, prefer, dry_run),
+        SubCommands::Export => export(config, output),
+        SubCommands::Import {
+            source,
+            file,
+            strategy,
+            from,
+            rewrite,
+            dry_run,
+        } => import(
+            config,
+            source,
+            file,
+            strategy.merge(),
+            from.map(|x| x.encoding()),
+            &rewrite,
+            dry_run,
+        ),
+        SubCommands::Preview { path } => preview(path),
+        SubCommands::GetAsLinkDirectory { tags } => get_as_link_directory(config, tags),
+        _ => Err(Error::Other("not yet implemented".to_owned())), // TODO
+    }
+}
+
+/// the database of the library that is used, it writes tags to the mirrors from the config
+fn open_database(config: &config::Config) -> Result<Database, Error> {
+    Ok(Database::open(config.clone().database_file)?.with_mirror(config.mirror))
+}
+
+fn config_command(
+    location: &config::Location,
+    output: &Output,
+    action: parse::ConfigAction,
+) -> Result<(), Error> {
+    match action {
+        parse::ConfigAction::Show => {
+            let records: Vec<ConfigRecord> = config::settings(location)?
+                .into_iter()
+                .map(|x| ConfigRecord {
+                    key: x.key.to_owned(),
+                    value: x.value,
+                    source: x.source.to_owned(),
+                })
+                .collect();
+            if !output.is_text() {
+                return output.records(&records);
+            }
+
+            let color = style::stdout_color();
+            let rows: Vec<Vec<String>> = records
+                .iter()
+                .map(|x| {
+                    vec![
+                        x.key.clone(),
+                        x.value.clone(),
+                        match x.source.as_str() {
+                            "default" => style::dimmed("default", color),
+                            "config" => String::new(),
+                            source => source.to_owned(),
+                        },
+                    ]
+                })
+                .collect();
+            table::print(&[], &rows, false)?;
         }
+        parse::ConfigAction::Get { key } => {
+            match config::settings(location)?
+                .into_iter()
+                .find(|x| x.key == key)
+            {
+                Some(setting) => println!("{}", setting.value),
+                None => return Err(config::unknown_key(&key)),
+            }
+        }
+        parse::ConfigAction::Set { key, value } => config::set(location, &key, &value)?,
+        parse::ConfigAction::Edit => {
+            if !location.path.exists() {
+                config::create_empty_config(&location.path)?;
+            }
+            loop {
+                open::Opener::find(&[], open::Action::Edit, &location.path, &[])?.run()?;
+                let e = match config::validate(location) {
+                    Ok(()) => break,
+                    Err(e) => e,
+                };
+                if !io::stdin().is_terminal() {
+                    return Err(e);
+                }
+                eprintln!("{}", e);
+                if !Confirm::new("edit it again?").with_default(true).prompt()? {
+                    return Err(e);
+                }
+            }
+        }
+        parse::ConfigAction::Path => print_path(&location.path)?,
     }
     Ok(())
 }
 
-fn remove_file(names: Vec<String>, config: &config::Config) -> Result<(), String> {
+fn libraries_command(
+    location: &config::Location,
+    output: &Output,
+    action: parse::LibraryAction,
+) -> Result<(), Error> {
+    match action {
+        parse::LibraryAction::List => {
+            let config = config::read(location)?;
+            let mut records: Vec<LibraryRecord> = vec![];
+            for library in &config.libraries {
+                let files = match library.database_file.exists() {
+                    true => Some(
+                        Database::open(library.database_file.clone())?
+                            .list_files()
+                            .len(),
+                    ),
+                    false => None,
+                };
+                records.push(LibraryRecord {
+                    name: library.name.clone(),
+                    managed_directory: style::escape_path(&library.managed_directory),
+                    database_file: style::escape_path(&library.database_file),
+                    files,
+                    default: library.name == config.default_library,
+                });
+            }
+            if !output.is_text() {
+                return output.records(&records);
+            }
+
+            let color = style::stdout_color();
+            let rows: Vec<Vec<String>> = records
+                .iter()
+                .map(|x| {
+                    vec![
+                        // the library that is used now
+                        if x.name == config.library {
+                            "*".to_owned()
+                        } else {
+                            String::new()
+                        },
+                        x.name.clone(),
+                        x.managed_directory.clone(),
+                        x.files.map_or("-".to_owned(), |x| x.to_string()),
+                        if x.default {
+                            style::dimmed("default", color)
+                        } else {
+                            String::new()
+                        },
+                    ]
+                })
+                .collect();
+            table::print(&["", "name", "directory", "files", ""], &rows, true)?;
+        }
+        parse::LibraryAction::Create { name, directory } => {
+            config::check_library_name(&name)?;
+            let directory = match directory {
+                Some(directory) => std::path::absolute(directory)?,
+                None => match dirs::home_dir() {
+                    Some(home) => home.join(format!("tagged-{}", name)),
+                    None => {
+                        return Err(Error::Config(
+                            "could not find the home directory, use --directory".to_owned(),
+                        ))
+                    }
+                },
+            };
+            config::add_library(location, &name, &directory)?;
+            fs::create_dir_all(&directory)?;
+            eprintln!(
+                "created the library '{}' in {}, use it with --library {}",
+                name,
+                style::escape_path(&directory),
+                name
+            );
+        }
+        parse::LibraryAction::Remove { name } => {
+            config::remove_library(location, &name)?;
+            eprintln!(
+                "removed the library '{}' from the config, its files and database are kept",
+                name
+            );
+        }
+    }
+    Ok(())
+}
+
+fn remove_tag(names: Vec<String>, config: &config::Config) -> Result<(), Error> {
+    open_database(config)?.delete_tags(names)
+}
+
+fn remove_file(names: Vec<OsString>, config: &config::Config) -> Result<(), Error> {
+    let database = open_database(config)?;
+    let mut files: Vec<StoredFile> = vec![];
     for name in names {
-        if let Err(e) = Database::open(config.clone().managed_directory)?.delete_file(name) {
-            return Err(e.to_string());
+        let file = select::resolve(&database, config.picker, &name)?;
+        if !files.iter().any(|x| x.path == file.path) {
+            files.push(file);
+        }
+    }
+    let paths: Vec<PathBuf> = files.iter().map(|x| x.path.clone()).collect();
+    database.delete_files(&paths)?;
+
+    for file in files {
+        // the file itself stays, but the user may not expect the link that points to it
+        if let (AddMode::Link, Some(original)) = (file.mode, &file.original) {
+            eprintln!(
+                "{} is still a symlink to {}",
+                style::escape_path(original),
+                style::escape_path(&file.path)
+            );
         }
     }
     Ok(())
 }
 
-fn set_tags(config: &config::Config, tags: Vec<String>, file: String) -> Result<(), String> {
-    let filter = tag_relations::parse_tags(&tags)?;
-    if let Err(e) = Database::open(config.clone().managed_directory)?.set_tags(file, filter) {
-        return Err(e.to_string());
+fn undo(config: &config::Config) -> Result<(), Error> {
+    match open_database(config)?.undo()? {
+        Some(entry) => println!("undid `{}` ({})", entry.command, entry.summary()),
+        None => println!("nothing to undo"),
     }
     Ok(())
 }
 
-fn add_tag(names: Vec<String>, config: &config::Config) -> Result<(), String> {
-    for name in names {
-        // TODO idk if validation should be here, should be moved to parse
-        if name.contains("/") || name.contains("+") || name.contains("-") || name.contains(" ") {
-            return Err("tag names cannot contain '/', '+', '-' or whitespace".to_owned());
+fn redo(config: &config::Config) -> Result<(), Error> {
+    match open_database(config)?.redo()? {
+        Some(entry) => println!("redid `{}` ({})", entry.command, entry.summary()),
+        None => println!("nothing to redo"),
+    }
+    Ok(())
+}
+
+fn history(config: &config::Config, output: &Output, limit: usize) -> Result<(), Error> {
+    let journal = open_database(config)?.history()?;
+    let skip = journal.entries.len().saturating_sub(limit);
+    let records: Vec<HistoryRecord> = journal
+        .entries
+        .iter()
+        .enumerate()
+        .skip(skip)
+        .map(|(i, entry)| HistoryRecord {
+            index: i + 1,
+            time: entry.time,
+            command: entry.command.clone(),
+            changes: entry.summary(),
+            undone: i >= journal.position,
+        })
+        .collect();
+
+    if !output.is_text() {
+        return output.records(&records);
+    }
+    if records.is_empty() {
+        println!("no changes yet");
+        return Ok(());
+    }
+
+    let color = style::stdout_color();
+    let rows: Vec<Vec<String>> = records
+        .iter()
+        .map(|x| {
+            let row = vec![
+                x.index.to_string(),
+                style::time(x.time),
+                x.command.clone(),
+                x.changes.clone(),
+                if x.undone {
+                    "undone".to_owned()
+                } else {
+                    String::new()
+                },
+            ];
+            if x.undone {
+                row.into_iter().map(|x| style::dimmed(&x, color)).collect()
+            } else {
+                row
+            }
+        })
+        .collect();
+    table::print(&["", "time", "command", "changes", ""], &rows, true)?;
+    Ok(())
+}
+
+fn dedupe(config: &config::Config, dry_run: bool) -> Result<(), Error> {
+    let report = open_database(config)?.dedupe(dry_run)?;
+    if report.is_empty() {
+        println!("no duplicates found");
+        return Ok(());
+    }
+
+    let verb = if dry_run { "would merge" } else { "merged" };
+    for (path, count) in &report.merged_files {
+        println!("{} {} entries of {}", verb, count, style::escape_path(path));
+    }
+    if report.canonical_paths > 0 {
+        let verb = if dry_run { "would rewrite" } else { "rewrote" };
+        println!(
+            "{} {} paths that were not canonical",
+            verb, report.canonical_paths
+        );
+    }
+    for (path, name) in &report.renamed_files {
+        let verb = if dry_run {
+            "would store it"
+        } else {
+            "stored it"
+        };
+        println!(
+            "{}: another file has the same name, {} as '{}'",
+            style::escape_path(path),
+            verb,
+            name
+        );
+    }
+    if report.removed_connections > 0 {
+        let verb = if dry_run { "would remove" } else { "removed" };
+        println!(
+            "{} {} repeated tag connections",
+            verb, report.removed_connections
+        );
+    }
+    for tag in &report.removed_tags {
+        let verb = if dry_run { "would merge" } else { "merged" };
+        println!("{} repeated tag {}", verb, tag);
+    }
+    Ok(())
+}
+
+/// a copied directory brings its sidecar along, the files in it are added with the tags from there
+fn check(config: &config::Config, roots: Vec<PathBuf>, dry_run: bool) -> Result<(), Error> {
+    let database = open_database(config)?;
+    let stored = database.list_files();
+
+    let mut missing = 0;
+    for file in &stored {
+        if fs::symlink_metadata(&file.path).is_err() {
+            missing += 1;
+            println!("missing: {}", style::escape_path(&file.path));
+        }
+    }
+
+    let roots = match roots.is_empty() {
+        true => vec![config.managed_directory.clone()],
+        false => roots,
+    };
+    let mut directories: Vec<PathBuf> = vec![];
+    for root in roots {
+        match fs::canonicalize(&root) {
+            Ok(root) => sidecar::find(&root, &mut directories),
+            Err(e) => {
+                return Err(Error::NotFound(format!(
+                    "could not find '{}', {}",
+                    style::escape_path(&root),
+                    e
+                )))
+            }
+        }
+    }
+
+    let mut new_files: Vec<(StoredFile, Vec<String>)> = vec![];
+    let mut file_tags: Vec<(String, Vec<String>)> = vec![];
+    let mut tagged = 0;
+    let mut invalid: Vec<String> = vec![];
+    for directory in &directories {
+        let sidecar = match sidecar::read(directory) {
+            Ok(sidecar) => sidecar.unwrap_or_default(),
+            Err(e) => {
+                eprintln!("{}", e);
+                continue;
+            }
+        };
+        for (name, tags) in sidecar.files {
+            let path = directory.join(&name);
+            // the file was not copied along
+            if fs::symlink_metadata(&path).is_err() {
+                continue;
+            }
+            let (tags, rest): (Vec<String>, Vec<String>) = tags
+                .into_iter()
+                .partition(|x| tag_relations::validate_tag_name(x).is_ok());
+            for tag in rest {
+                if !invalid.contains(&tag) {
+                    invalid.push(tag);
+                }
+            }
+
+            // a copied directory has files with the same names, they get names of their own when they are added
+            let file = match stored.iter().find(|x| x.path == path) {
+                Some(file) => file,
+                None => {
+                    let file = StoredFile {
+                        name,
+                        path,
+                        last_used: now(),
+                        use_count: 0,
+                        mode: AddMode::None,
+                        original: None,
+                    };
+                    new_files.push((file, tags));
+                    continue;
+                }
+            };
+
+            let index = match file_tags.iter().position(|x| x.0 == file.name) {
+                Some(index) => index,
+                None => {
+                    file_tags.push((file.name.clone(), database.file_tag_names(&file.name)));
+                    file_tags.len() - 1
+                }
+            };
+            let new_tags: Vec<String> = tags
+                .into_iter()
+                .filter(|x| !file_tags[index].1.contains(x))
+                .collect();
+            if !new_tags.is_empty() {
+                tagged += 1;
+            }
+            file_tags[index].1.extend(new_tags);
+        }
+    }
+
+    if !invalid.is_empty() {
+        eprintln!(
+            "these tags from sidecars are not valid tag names and were left out: {}",
+            invalid.join(", ")
+        );
+    }
+    println!(
+        "{} files are missing, found {} sidecars, {} {} files and tags to {} files",
+        missing,
+        directories.len(),
+        if dry_run { "would add" } else { "added" },
+        new_files.len(),
+        tagged
+    );
+    if dry_run {
+        return Ok(());
+    }
+
+    if !new_files.is_empty() || tagged > 0 {
+        database.sync_tags(new_files, file_tags)?;
+    }
+    // directories that had tagged files before sidecars were turned on get theirs now
+    if config.mirror.sidecar {
+        let database = open_database(config)?;
+        let files = database.list_files();
+        let mut directories: Vec<&Path> = vec![];
+        for directory in files.iter().filter_map(|x| x.path.parent()) {
+            if !directories.contains(&directory) {
+                directories.push(directory);
+            }
         }
-        if let Err(e) = Database::open(config.clone().managed_directory)?.add_tag(name) {
-            return Err(e.to_string());
+        for (directory, e) in database.write_sidecars(&directories) {
+            eprintln!(
+                "could not write the sidecar in {}, {}",
+                style::escape_path(&directory),
+                e
+            );
         }
     }
     Ok(())
 }
 
-fn add_file(
-    file_path: PathBuf,
+/// reads the tags other programs wrote to files, and writes the tags from the database for them
+fn sync_xattr(
+    config: &config::Config,
+    paths: Vec<PathBuf>,
+    prefer: parse::XattrPrefer,
+    dry_run: bool,
+) -> Result<(), Error> {
+    let database = open_database(config)?;
+    let stored = database.list_files();
+
+    let mut unsupported = 0;
+    let mut invalid: Vec<String> = vec![];
+    // `None` when the file has no tags there or they can not be read
+    let mut read = |path: &Path| -> Option<Vec<String>> {
+        match xdg_tags::read(path) {
+            Ok(tags) => {
+                let (valid, rest) = xdg_tags::split(tags?);
+                for tag in rest {
+                    if !invalid.contains(&tag) {
+                        invalid.push(tag);
+                    }
+                }
+                Some(valid)
+            }
+            Err(e) if e.kind() == io::ErrorKind::Unsupported => {
+                unsupported += 1;
+                None
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => {
+                eprintln!(
+                    "could not read the tags of {}, {}",
+                    style::escape_path(path),
+                    e
+                );
+                None
+            }
+        }
+    };
+
+    let mut new_files: Vec<(StoredFile, Vec<String>)> = vec![];
+    // the tags files get in the database, and in the attribute
+    let mut file_tags: Vec<(String, Vec<String>)> = vec![];
+    let mut writes: Vec<(PathBuf, Vec<String>)> = vec![];
+
+    for file in &stored {
+        let here = database.file_tag_names(&file.name);
+        let theirs = match read(&file.path) {
+            Some(theirs) => theirs,
+            None => {
+                if !here.is_empty() && fs::symlink_metadata(&file.path).is_ok() {
+                    writes.push((file.path.clone(), here));
+                }
+                continue;
+            }
+        };
+        if here.len() == theirs.len() && here.iter().all(|x| theirs.contains(x)) {
+            continue;
+        }
+
+        let tags = match prefer {
+            parse::XattrPrefer::Union => {
+                let mut tags = here.clone();
+                tags.extend(theirs.iter().filter(|x| !here.contains(x)).cloned());
+                tags
+            }
+            parse::XattrPrefer::Database => here.clone(),
+            parse::XattrPrefer::Xattr => theirs.clone(),
+        };
+        if !here.is_empty() && !theirs.is_empty() {
+            println!(
+                "{}: '{}' in the database, '{}' in the attribute, {} '{}'",
+                style::escape_path(&file.path),
+                here.join(" "),
+                theirs.join(" "),
+                if dry_run { "would use" } else { "using" },
+                tags.join(" ")
+            );
+        }
+        if tags != here {
+            file_tags.push((file.name.clone(), tags.clone()));
+        }
+        if tags != theirs {
+            writes.push((file.path.clone(), tags));
+        }
+    }
+
+    let mut found: Vec<PathBuf> = vec![];
+    for path in paths {
+        let path = match fs::canonicalize(&path) {
+            Ok(path) => path,
+            Err(e) => {
+                return Err(Error::NotFound(format!(
+                    "could not find '{}', {}",
+                    style::escape_path(&path),
+                    e
+                )))
+            }
+        };
+        if path.is_dir() {
+            walk_directory(&path, &[], &[], &mut found);
+        } else {
+            found.push(path);
+        }
+    }
+    for path in found {
+        if stored.iter().any(|x| x.path == path) || new_files.iter().any(|x| x.0.path == path) {
+            continue;
+        }
+        let (name, tags) = match (StoredFile::name_of(&path), read(&path)) {
+            (Some(name), Some(tags)) if !tags.is_empty() => (name, tags),
+            _ => continue,
+        };
+        let file = StoredFile {
+            name,
+            path,
+            last_used: now(),
+            use_count: 0,
+            mode: AddMode::None,
+            original: None,
+        };
+        new_files.push((file, tags));
+    }
+
+    if unsupported > 0 {
+        eprintln!(
+            "{} files are on a filesystem without extended attributes, they were skipped",
+            unsupported
+        );
+    }
+    if !invalid.is_empty() {
+        eprintln!(
+            "these tags from the attribute are not valid tag names and were left out: {}",
+            invalid.join(", ")
+        );
+    }
+
+    let (added, changed) = (new_files.len(), file_tags.len());
+    if dry_run {
+        println!(
+            "would add {} files, would change the tags of {} files in the database and {} in the attribute",
+            added,
+            changed,
+            writes.len()
+        );
+        return Ok(());
+    }
+
+    if added > 0 || changed > 0 {
+        database.sync_tags(new_files, file_tags)?;
+    }
+    let mut written = 0;
+    for (path, tags) in writes {
+        match xdg_tags::write(&path, &tags) {
+            Ok(()) => written += 1,
+            Err(e) => eprintln!(
+                "could not write the tags of {}, {}",
+                style::escape_path(&path),
+                e
+            ),
+        }
+    }
+    println!(
+        "added {} files, changed the tags of {} files in the database and {} in the attribute",
+        added, changed, written
+    );
+    Ok(())
+}
+
+fn export(config: &config::Config, output: &Output) -> Result<(), Error> {
+    let encoding = match output.format() {
+        output::Format::Text | output::Format::Json => Encoding::Json,
+        output::Format::Csv => Encoding::Csv,
+        output::Format::Ron => Encoding::Ron,
+        _ => {
+            return Err(Error::Invalid(
+                "export can only be written as json, csv or ron".to_owned(),
+            ))
+        }
+    };
+    let mut text = open_database(config)?.export().write(encoding)?;
+    if !text.ends_with('\n') {
+        text.push('\n');
+    }
+
+    let mut stdout = io::stdout().lock();
+    match stdout.write_all(text.as_bytes()) {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// reads an export or the tags of another program, and checks all of it before anything is merged, so a broken file changes nothing
+#[allow(clippy::too_many_arguments)]
+fn import(
+    config: &config::Config,
+    source: Option<parse::ImportSource>,
+    file: Option<PathBuf>,
+    merge: Merge,
+    encoding: Option<Encoding>,
+    rewrites: &[Rewrite],
+    dry_run: bool,
+) -> Result<(), Error> {
+    let mut incoming = match (source, file) {
+        (Some(parse::ImportSource::Tmsu { database }), _) => {
+            let (export, mapping) = migrate::tmsu(&database)?;
+            print_mapping("tmsu", &mapping);
+            export
+        }
+        (Some(parse::ImportSource::Tagspaces { directory }), _) => {
+            let (export, mapping) = migrate::tagspaces(&directory)?;
+            print_mapping("tagspaces", &mapping);
+            export
+        }
+        (None, Some(file)) => read_export(&file, encoding)?,
+        // clap requires one of them
+        (None, None) => return Err(Error::Invalid("nothing to import".to_owned())),
+    };
+    incoming.rewrite(rewrites);
+    incoming.validate()?;
+    let report = open_database(config)?.import(incoming, merge, dry_run)?;
+
+    let kept = match (dry_run, merge) {
+        (true, Merge::PreferIncoming) => "would take",
+        (true, _) => "would keep",
+        (false, Merge::PreferIncoming) => "took",
+        (false, _) => "kept",
+    };
+    for conflict in &report.conflicts {
+        let value = match merge {
+            Merge::PreferIncoming => &conflict.incoming,
+            _ => &conflict.here,
+        };
+        println!(
+            "conflict in {} {}: '{}' here, '{}' in the import, {} '{}'",
+            conflict.subject, conflict.field, conflict.here, conflict.incoming, kept, value
+        );
+    }
+    for (path, name) in &report.renamed_files {
+        println!(
+            "{}: another file already has its name, {} as '{}'",
+            style::escape_path(path),
+            if dry_run {
+                "would store it"
+            } else {
+                "stored it"
+            },
+            name
+        );
+    }
+    if !report.missing_files.is_empty() {
+        eprintln!(
+            "{} imported files do not exist here, e.g. {}, maybe a --rewrite is missing",
+            report.missing_files.len(),
+            style::escape_path(&report.missing_files[0])
+        );
+    }
+
+    if report.is_empty() {
+        println!("nothing to import, the database already has everything");
+        return Ok(());
+    }
+    let parts: Vec<String> = [
+        (report.added_files, "files", "add", "added"),
+        (report.updated_files, "files", "update", "updated"),
+        (report.removed_files, "files", "remove", "removed"),
+        (report.added_tags, "tags", "add", "added"),
+        (report.updated_tags, "tags", "update", "updated"),
+        (report.removed_tags, "tags", "remove", "removed"),
+    ]
+    .into_iter()
+    .filter(|x| x.0 > 0)
+    .map(|(count, what, verb, past)| match dry_run {
+        true => format!("{} {} {}", verb, count, what),
+        false => format!("{} {} {}", past, count, what),
+    })
+    .collect();
+    match dry_run {
+        true => println!("would {}", parts.join(", ")),
+        false => println!("{}", parts.join(", ")),
+    }
+    Ok(())
+}
+
+fn read_export(file: &Path, encoding: Option<Encoding>) -> Result<Export, Error> {
+    if file == Path::new("-") {
+        let mut text = String::new();
+        io::stdin().read_to_string(&mut text)?;
+        return Export::read(&text, encoding);
+    }
+
+    let text = fs::read_to_string(file).map_err(|e| {
+        Error::from(io::Error::new(
+            e.kind(),
+            format!("could not read {}, {}", file.display(), e),
+        ))
+    })?;
+    let guessed = match file.extension().and_then(|x| x.to_str()) {
+        Some("json") => Some(Encoding::Json),
+        Some("csv") => Some(Encoding::Csv),
+        Some("ron") => Some(Encoding::Ron),
+        _ => None,
+    };
+    Export::read(&text, encoding.or(guessed))
+}
+
+/// what was read from another program, and what had to change to fit in here
+fn print_mapping(program: &str, mapping: &migrate::Mapping) {
+    println!(
+        "read {} files with {} tags from {}",
+        mapping.files, mapping.tags, program
+    );
+    if mapping.value_tags > 0 {
+        println!(
+            "{} tags with a value became two tags, like `year` and `year:2017`",
+            mapping.value_tags
+        );
+    }
+    for (name, mapped) in &mapping.renamed {
+        println!("renamed the tag '{}' to '{}'", name, mapped);
+    }
+    for note in &mapping.notes {
+        eprintln!("{}", note);
+    }
+}
+
+/// moves the files in the managed directory to where the layout puts them
+///
+/// either all files are moved and the database is updated, or everything that was moved is moved back
+fn reorganize(config: &config::Config, dry_run: bool) -> Result<(), Error> {
+    let database = open_database(config)?;
+    let link_directory = config.managed_directory.join(&config.link_directory_name);
+
+    let mut moves: Vec<FileMove> = vec![];
+    for file in database.list_files() {
+        // files that were added without moving them are not touched
+        if !file.path.starts_with(&config.managed_directory)
+            || file.path.starts_with(&link_directory)
+        {
+            continue;
+        }
+        let metadata = match fs::symlink_metadata(&file.path) {
+            Ok(metadata) => metadata,
+            Err(_) => {
+                eprintln!(
+                    "skipping '{}', it does not exist",
+                    style::escape_path(&file.path)
+                );
+                continue;
+            }
+        };
+        let name = match file.path.file_name() {
+            Some(name) => name,
+            None => continue,
+        };
+
+        let tags = database.file_tag_names(&file.name);
+        let placement = layout::Placement {
+            name,
+            tags: &tags,
+            modified: modified_epoch(&metadata),
+        };
+        let mut target = config
+            .managed_directory
+            .join(config.layout.path(&placement));
+        if target == file.path {
+            continue;
+        }
+        // another file is already there, or will be
+        if fs::symlink_metadata(&target).is_ok() || moves.iter().any(|x| x.to == target) {
+            let directory = target.parent().unwrap_or(&config.managed_directory);
+            target = transfer::free_name_with(directory, Path::new(name), |path| {
+                moves.iter().any(|x| x.to == path)
+            });
+        }
+        moves.push(FileMove {
+            from: file.path.clone(),
+            to: target,
+            mode: AddMode::Move,
+        });
+    }
+
+    if moves.is_empty() {
+        eprintln!("all files are where the layout puts them");
+        return Ok(());
+    }
+
+    if dry_run {
+        for file_move in &moves {
+            println!(
+                "{} -> {}",
+                style::escape_path(&file_move.from),
+                style::escape_path(&file_move.to)
+            );
+        }
+        eprintln!("would move {} files", moves.len());
+        return Ok(());
+    }
+
+    // symlinks to the old paths, from the link directory and from files added with `--option link`
+    let mut links: Vec<(PathBuf, PathBuf)> = vec![];
+    for file_move in &moves {
+        for view in find_views(config, &file_move.from) {
+            links.push((view, file_move.to.clone()));
+        }
+        for file in database.list_files() {
+            if let (AddMode::Link, true, Some(original)) =
+                (file.mode, file.path == file_move.from, &file.original)
+            {
+                if fs::read_link(original).is_ok_and(|x| x == file_move.from) {
+                    links.push((original.clone(), file_move.to.clone()));
+                }
+            }
+        }
+    }
+
+    for (i, file_move) in moves.iter().enumerate() {
+        let moved = match file_move.to.parent() {
+            Some(parent) => fs::create_dir_all(parent),
+            None => Ok(()),
+        }
+        .and_then(|_| transfer::move_path(&file_move.from, &file_move.to));
+        if let Err(e) = moved {
+            move_back(&moves[..i]);
+            return Err(Error::Io(format!(
+                "could not move {} to {}, {}, nothing was changed",
+                style::escape_path(&file_move.from),
+                style::escape_path(&file_move.to),
+                e
+            )));
+        }
+    }
+
+    let moved = moves.len();
+    if let Err(e) = database.move_files(moves.clone()) {
+        move_back(&moves);
+        return Err(e);
+    }
+
+    for (link, target) in links {
+        if let Err(e) = fs::remove_file(&link).and_then(|_| symlink_auto(&target, &link)) {
+            eprintln!(
+                "could not point {} to {}, {}",
+                style::escape_path(&link),
+                style::escape_path(&target),
+                e
+            );
+        }
+    }
+
+    // directories that were emptied by the moves are removed, up to the managed directory
+    for file_move in &moves {
+        let mut directory = file_move.from.parent();
+        while let Some(path) = directory {
+            if path == config.managed_directory || fs::remove_dir(path).is_err() {
+                break;
+            }
+            directory = path.parent();
+        }
+    }
+
+    eprintln!("moved {} files", moved);
+    Ok(())
+}
+
+/// undoes moves, copies and links that already happened, the last one first
+fn move_back(moves: &[FileMove]) {
+    for file_move in moves.iter().rev() {
+        if let Err(e) = transfer::revert(&file_move.from, &file_move.to, file_move.mode) {
+            eprintln!(
+                "could not move {} back to {}, {}",
+                style::escape_path(&file_move.to),
+                style::escape_path(&file_move.from),
+                e
+            );
+        }
+    }
+}
+
+fn set_tags(
+    config: &config::Config,
+    tags: Vec<String>,
+    file: OsString,
+    unknown: parse::UnknownTags,
+) -> Result<(), Error> {
+    let mut filter = tag_relations::parse_tags(&tags)?;
+    let database = open_database(config)?;
+    let file = select::resolve(&database, config.picker, &file)?;
+    let created = ensure_tags(&database, &mut filter, unknown)?;
+    database.with_tags(&created).set_tags(&[file.path], filter)
+}
+
+/// decides for every allowed tag in the filter that does not exist if it is created or dropped from the filter
+///
+/// returns the tags to create, they are created with `Database::with_tags` in the same change that uses them
+fn ensure_tags(
+    database: &Database,
+    filter: &mut TagFilter,
+    unknown: parse::UnknownTags,
+) -> Result<Vec<String>, Error> {
+    let missing = database.missing_tags(&filter.allowed_tags);
+    let mut created = vec![];
+
+    for tag in missing {
+        let create = match unknown {
+            parse::UnknownTags::Fail => {
+                return Err(Error::NotFound(format!(
+                    "the tag '{}' does not exist, create it with addtag or use --create-tags",
+                    tag
+                )))
+            }
+            parse::UnknownTags::Create => true,
+            parse::UnknownTags::Ask if !io::stdin().is_terminal() => {
+                eprintln!("the tag '{}' does not exist, skipping it", tag);
+                false
+            }
+            parse::UnknownTags::Ask => {
+                Confirm::new(&format!("The tag '{}' does not exist, create it?", tag))
+                    .with_default(true)
+                    .prompt()?
+            }
+        };
+
+        if create {
+            tag_relations::validate_tag_name(&tag)?;
+            created.push(tag);
+        } else {
+            filter.allowed_tags.retain(|x| *x != tag);
+        }
+    }
+    Ok(created)
+}
+
+fn add_tag(names: Vec<String>, config: &config::Config) -> Result<(), Error> {
+    for name in &names {
+        tag_relations::validate_tag_name(name)?;
+    }
+    open_database(config)?.add_tags(names)
+}
+
+fn edit_tag(
+    config: &config::Config,
+    name: String,
+    description: Option<String>,
+    color: Option<String>,
+    icon: Option<String>,
+) -> Result<(), Error> {
+    if description.is_none() && color.is_none() && icon.is_none() {
+        return Err(Error::Invalid(
+            "nothing to change, give at least one of --description, --color or --icon".to_owned(),
+        ));
+    }
+    open_database(config)?.edit_tag(name, description, color, icon)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn add_files(
     config: &config::Config,
+    paths: Vec<OsString>,
     option: parse::AddFileOptions,
-) -> Result<(), String> {
-    let mut file_path = file_path.canonicalize().unwrap();
+    on_conflict: Option<parse::Conflict>,
+    tags: Vec<String>,
+    recursive: bool,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    stdin: bool,
+    unknown: parse::UnknownTags,
+) -> Result<(), Error> {
+    let mut arguments = paths;
+    if stdin {
+        // read as bytes, paths dont have to be valid utf-8
+        for line in io::stdin().lock().split(b'\n') {
+            let line = line?;
+            let line = line.strip_suffix(b"\r").unwrap_or(&line);
+            if !line.is_empty() {
+                arguments.push(os_string_from_bytes(line.to_vec()));
+            }
+        }
+    }
 
-    let temp = file_path.clone();
-    let file_name = temp.file_name().unwrap();
+    let include = compile_patterns(&include)?;
+    let exclude = compile_patterns(&exclude)?;
 
-    match option {
-        parse::AddFileOptions::None => {
-            // i think this should be empty
+    let mut found: Vec<PathBuf> = vec![];
+    for argument in arguments {
+        for path in expand_argument(&argument)? {
+            if recursive && path.is_dir() {
+                walk_directory(&path, &include, &exclude, &mut found);
+            } else {
+                found.push(path);
+            }
         }
-        parse::AddFileOptions::Move => {
-            let mut final_file_path = PathBuf::new();
-            final_file_path.push(config.clone().managed_directory);
-            final_file_path.push(file_name);
+    }
 
-            if let Err(e) = better_copy(file_path.clone(), final_file_path.clone()) {
-                return Err(e.to_string());
+    // duplicates in the input and files that are already stored are skipped before anything is moved
+    let database = open_database(config)?;
+    let mut files: Vec<PathBuf> = vec![];
+    for path in found {
+        let path = match path.canonicalize() {
+            Ok(path) => path,
+            Err(e) => {
+                return Err(Error::NotFound(format!(
+                    "could not find '{}', {}",
+                    path.display(),
+                    e
+                )))
             }
-            file_path = final_file_path; //change filepath so it actually changes in the database as this path
-            if let Err(e) = better_delete(file_path.clone()) {
-                return Err(e.to_string());
+        };
+        if files.contains(&path) {
+            continue;
+        }
+        if !select::matching(&database, path.as_os_str()).is_empty() {
+            eprintln!(
+                "skipping '{}', it is already tracked",
+                style::escape_path(&path)
+            );
+            continue;
+        }
+        files.push(path);
+    }
+    if files.is_empty() {
+        eprintln!("no new files to add");
+        return Ok(());
+    }
+
+    let mut filter = tag_relations::parse_tags(&tags)?;
+    let created = ensure_tags(&database, &mut filter, unknown)?;
+
+    let mode = option.mode();
+    let mut entries: Vec<StoredFile> = vec![];
+    let mut moves: Vec<FileMove> = vec![];
+    // stored paths whose file was overwritten, their entries are replaced by the new ones
+    let mut replaced: Vec<PathBuf> = vec![];
+    for original_path in files {
+        let moved = moves.len();
+        let file_path = match add_file(
+            original_path.clone(),
+            config,
+            mode,
+            on_conflict,
+            &filter.allowed_tags,
+            &mut moves,
+        ) {
+            Ok(Some(file_path)) => file_path,
+            Ok(None) => continue,
+            // nothing is stored yet, the files that were already put in place go back
+            Err(e) => {
+                move_back(&moves);
+                return Err(e);
             }
+        };
+        if moves.len() > moved {
+            replaced.push(file_path.clone());
         }
+        let original = if file_path != original_path {
+            moves.push(FileMove {
+                from: original_path.clone(),
+                to: file_path.clone(),
+                mode,
+            });
+            Some(original_path)
+        } else {
+            None
+        };
+        let file_name = match StoredFile::name_of(&file_path) {
+            Some(name) => name,
+            None => {
+                move_back(&moves);
+                return Err(Error::Invalid(format!(
+                    "'{}' has no file name",
+                    style::escape_path(&file_path)
+                )));
+            }
+        };
+        entries.push(StoredFile {
+            name: file_name,
+            path: file_path,
+            last_used: now(),
+            use_count: 0,
+            mode: if original.is_some() {
+                mode
+            } else {
+                AddMode::None
+            },
+            original,
+        });
     }
 
-    if let Err(e) = Database::open(config.clone().managed_directory)?.add_file(
-        file_name.to_str().unwrap().to_owned(), // TODO see if you can make this less ugly everytime
-        file_path.to_str().unwrap().to_owned(),
-        0, // TODO date epoch
-    ) {
-        return Err(e.to_string());
+    let added = entries.len();
+    let stored = open_database(config).and_then(|database| {
+        database
+            .with_moves(moves.clone())
+            .with_tags(&created)
+            .add_files(entries, &filter.allowed_tags, &replaced)
+    });
+    let skipped = match stored {
+        Ok(skipped) => skipped,
+        Err(e) => {
+            move_back(&moves);
+            return Err(e);
+        }
+    };
+    for path in &skipped {
+        eprintln!(
+            "skipping '{}', it is already tracked",
+            style::escape_path(path)
+        );
+    }
+    if added > 1 {
+        eprintln!("added {} files", added - skipped.len());
     }
     Ok(())
 }
 
-fn get_file_path(
+/// an existing path as it is, otherwise it is used as a glob pattern
+fn expand_argument(argument: &OsStr) -> Result<Vec<PathBuf>, Error> {
+    let path = PathBuf::from(argument);
+    if fs::symlink_metadata(&path).is_ok() {
+        return Ok(vec![path]);
+    }
+    // glob only works on utf-8, other paths can only be given literally
+    let argument = match argument.to_str() {
+        Some(argument) if argument.contains(['*', '?', '[']) => argument,
+        _ => {
+            return Err(Error::NotFound(format!(
+                "'{}' does not exist",
+                style::escape_path(&path)
+            )))
+        }
+    };
+
+    let matches = match glob::glob(argument) {
+        Ok(matches) => matches,
+        Err(e) => {
+            return Err(Error::Invalid(format!(
+                "invalid pattern '{}', {}",
+                argument, e
+            )))
+        }
+    };
+    let paths: Vec<PathBuf> = matches.flatten().collect();
+    if paths.is_empty() {
+        eprintln!("'{}' did not match any files", argument);
+    }
+    Ok(paths)
+}
+
+fn compile_patterns(patterns: &[String]) -> Result<Vec<glob::Pattern>, Error> {
+    patterns
+        .iter()
+        .map(|x| {
+            glob::Pattern::new(x)
+                .map_err(|e| Error::Invalid(format!("invalid pattern '{}', {}", x, e)))
+        })
+        .collect()
+}
+
+/// a pattern matches either the file name or the whole path
+fn matches_any(patterns: &[glob::Pattern], path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .map(|x| x.to_string_lossy())
+        .unwrap_or_default();
+    patterns
+        .iter()
+        .any(|x| x.matches(&name) || x.matches_path(path))
+}
+
+/// all files below the directory, excluded directories are not entered and sidecars are left out
+fn walk_directory(
+    directory: &Path,
+    include: &[glob::Pattern],
+    exclude: &[glob::Pattern],
+    found: &mut Vec<PathBuf>,
+) {
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("could not read '{}', {}", directory.display(), e);
+            return;
+        }
+    };
+
+    let mut paths: Vec<PathBuf> = entries.flatten().map(|x| x.path()).collect();
+    paths.sort();
+    for path in paths {
+        if matches_any(exclude, &path) || sidecar::is_sidecar(&path) {
+            continue;
+        }
+        if path.is_dir() {
+            walk_directory(&path, include, exclude, found);
+        } else if include.is_empty() || matches_any(include, &path) {
+            found.push(path);
+        }
+    }
+}
+
+/// puts the file in the managed directory the way the mode says, returns where the file is now or `None` if it is skipped
+///
+/// a file that is overwritten is moved to `.replaced` in the managed directory first, that move is added to `moves` so `undo` brings it back
+fn add_file(
+    file_path: PathBuf,
     config: &config::Config,
+    mode: AddMode,
+    on_conflict: Option<parse::Conflict>,
+    tags: &[String],
+    moves: &mut Vec<FileMove>,
+) -> Result<Option<PathBuf>, Error> {
+    if mode == AddMode::None {
+        return Ok(Some(file_path));
+    }
+
+    if mode == AddMode::Hardlink && file_path.is_dir() {
+        return Err(Error::Invalid(format!(
+            "cant hard link the directory {}, use copy or link instead",
+            style::escape_path(&file_path)
+        )));
+    }
+
+    let file_name = match file_path.file_name() {
+        Some(name) => name,
+        None => {
+            return Err(Error::Invalid(format!(
+                "'{}' has no file name",
+                style::escape_path(&file_path)
+            )))
+        }
+    };
+    let placement = layout::Placement {
+        name: file_name,
+        tags,
+        modified: fs::symlink_metadata(&file_path)
+            .ok()
+            .as_ref()
+            .and_then(modified_epoch),
+    };
+    let mut final_file_path = config
+        .managed_directory
+        .join(config.layout.path(&placement));
+    if final_file_path == file_path {
+        return Ok(Some(file_path));
+    }
+    let final_directory = final_file_path
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| config.managed_directory.clone());
+    fs::create_dir_all(&final_directory)?;
+
+    let mut replaced: Option<FileMove> = None;
+    if fs::symlink_metadata(&final_file_path).is_ok() {
+        let conflict = match on_conflict {
+            Some(conflict) => conflict,
+            None if !io::stdin().is_terminal() => {
+                return Err(Error::Invalid(format!(
+                    "{} already exists, use --on-conflict to skip, rename or overwrite",
+                    style::escape_path(&final_file_path)
+                )))
+            }
+            None => Select::new(
+                &format!(
+                    "{} already exists, what to do?",
+                    style::escape_path(&final_file_path)
+                ),
+                parse::Conflict::ALL.to_vec(),
+            )
+            .prompt()?,
+        };
+        match conflict {
+            parse::Conflict::Skip => {
+                eprintln!(
+                    "skipping '{}', {} already exists",
+                    style::escape_path(&file_path),
+                    style::escape_path(&final_file_path)
+                );
+                return Ok(None);
+            }
+            parse::Conflict::Rename => {
+                final_file_path = transfer::free_name(&final_directory, Path::new(file_name))
+            }
+            parse::Conflict::Overwrite => {
+                // kept until the file is in place, so a failed transfer loses nothing
+                let directory = config.managed_directory.join(".replaced");
+                fs::create_dir_all(&directory)?;
+                let mut backup = directory.join(file_name);
+                if fs::symlink_metadata(&backup).is_ok() {
+                    backup = transfer::free_name(&directory, Path::new(file_name));
+                }
+                let replaced_move = FileMove {
+                    from: final_file_path.clone(),
+                    to: backup,
+                    mode: AddMode::Move,
+                };
+                transfer::move_path(&replaced_move.from, &replaced_move.to)?;
+                replaced = Some(replaced_move);
+            }
+        }
+    }
+
+    if let Err(e) = transfer::transfer(&file_path, &final_file_path, mode) {
+        if let Some(replaced) = &replaced {
+            move_back(std::slice::from_ref(replaced));
+        }
+        return Err(Error::Io(format!(
+            "could not {} {} to {}, {}",
+            mode.name(),
+            style::escape_path(&file_path),
+            style::escape_path(&final_file_path),
+            e
+        )));
+    }
+    if let Some(replaced) = replaced {
+        eprintln!(
+            "replaced {}, the old file is at {} until you remove it",
+            style::escape_path(&replaced.from),
+            style::escape_path(&replaced.to)
+        );
+        moves.push(replaced);
+    }
+    Ok(Some(final_file_path))
+}
+
+/// a file with its tags and the library it is in
+type LibraryFile<'a> = (&'a config::Library, StoredFile, Vec<Tag>);
+
+/// the files matching the tags, after asking the user to choose one unless `multiple` is set
+///
+/// `hint` says how to avoid the question when there is no terminal to ask it
+fn choose_files<'a>(
+    config: &'a config::Config,
     tags: Option<Vec<String>>,
     multiple: bool,
-) -> Result<(), String> {
-    let tags = match tags {
-        Some(list) => list,
-        None => vec![],
-    };
+    all_libraries: bool,
+    hint: &str,
+) -> Result<Vec<LibraryFile<'a>>, Error> {
+    let tags = tags.unwrap_or_default();
 
     // TODO actually use these tags
     let filter = tag_relations::parse_tags(&tags)?;
 
-    let files = Database::open(config.clone().managed_directory)?.get_files(filter);
-    if let Err(e) = files {
-        return Err(e.to_string());
+    let mut options = library_files(config, filter, all_libraries)?;
+    if options.is_empty() {
+        return Err(Error::NotFound("no files match".to_owned()));
     }
-    let actual_files = files.unwrap();
 
-    if actual_files.is_empty() {
-        return Err("no files match".to_owned());
-    }
     if multiple {
-        for file in actual_files {
-            println!("{}", file.path);
-            // this keeps a trailing space, dont think it will be a problem
+        return Ok(options);
+    }
+    let file = prompt::choose_file(
+        options
+            .iter()
+            .map(|(_, file, tags)| (file.clone(), tags.clone()))
+            .collect(),
+        config.picker,
+        hint,
+    )?;
+    let i = options
+        .iter()
+        .position(|x| x.1.path == file.path)
+        .unwrap_or(0);
+    Ok(vec![options.swap_remove(i)])
+}
+
+/// the files matching the filter with their tags, from the library in use or from every library
+///
+/// libraries without a database are skipped, so listing them does not create one
+fn library_files(
+    config: &config::Config,
+    filter: TagFilter,
+    all_libraries: bool,
+) -> Result<Vec<LibraryFile<'_>>, Error> {
+    let mut result = vec![];
+    for library in &config.libraries {
+        let used = library.name == config.library;
+        if !used && (!all_libraries || !library.database_file.exists()) {
+            continue;
+        }
+        let database = Database::open(library.database_file.clone())?;
+        for file in database.get_files(filter.clone())? {
+            let tags = database.get_file_tags(&file.name);
+            result.push((library, file, tags));
+        }
+    }
+    Ok(result)
+}
+
+fn get_file_path(
+    config: &config::Config,
+    output: &Output,
+    tags: Option<Vec<String>>,
+    multiple: bool,
+    all_libraries: bool,
+) -> Result<(), Error> {
+    let chosen = choose_files(
+        config,
+        tags,
+        multiple,
+        all_libraries,
+        "use --multiple or more specific tags",
+    )?;
+    if !multiple {
+        let (library, file, _) = &chosen[0];
+        Database::open(library.database_file.clone())?.mark_used(&file.path, now())?;
+    }
+
+    if output.is_text() {
+        for (_, file, _) in chosen {
+            print_path(&file.path)?;
+        }
+        return Ok(());
+    }
+    let records: Vec<FileRecord> = chosen
+        .iter()
+        .map(|(library, file, tags)| FileRecord::new(file, tags, &library.name))
+        .collect();
+    output.records(&records)
+}
+
+fn open_file(
+    config: &config::Config,
+    tags: Option<Vec<String>>,
+    action: open::Action,
+    print_command: bool,
+) -> Result<(), Error> {
+    let (_, file, tags) =
+        choose_files(config, tags, false, false, "pass more specific tags")?.remove(0);
+    let opener = open::Opener::find(&config.openers, action, &file.path, &tags)?;
+
+    if print_command {
+        println!("{}", opener.display());
+        return Ok(());
+    }
+    open_database(config)?.mark_used(&file.path, now())?;
+    opener.run()
+}
+
+fn select_files(
+    config: &config::Config,
+    output: &Output,
+    tags: Option<Vec<String>>,
+    action: Option<parse::BulkAction>,
+    mut change_tags: Vec<String>,
+    unknown: parse::UnknownTags,
+) -> Result<(), Error> {
+    let filter = tag_relations::parse_tags(&tags.unwrap_or_default())?;
+    let database = open_database(config)?;
+    let files = database.get_files(filter)?;
+    if files.is_empty() {
+        return Err(Error::NotFound("no files match".to_owned()));
+    }
+    let chosen = select::ask_many(&database, config.picker, files)?;
+
+    let action = match action {
+        Some(action) => action,
+        None => Select::new(
+            &format!("What to do with {} files?", chosen.len()),
+            parse::BulkAction::ALL.to_vec(),
+        )
+        .prompt()?,
+    };
+
+    if matches!(action, parse::BulkAction::Tag | parse::BulkAction::Untag) && change_tags.is_empty()
+    {
+        match Text::new("Which tags? (seperated by spaces)").prompt() {
+            Ok(answer) => change_tags = answer.split_whitespace().map(|x| x.to_owned()).collect(),
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    match action {
+        parse::BulkAction::Print => {
+            if output.is_text() {
+                for file in chosen {
+                    print_path(&file.path)?;
+                }
+                return Ok(());
+            }
+            let records: Vec<FileRecord> = chosen
+                .iter()
+                .map(|x| FileRecord::new(x, &database.get_file_tags(&x.name), &config.library))
+                .collect();
+            output.records(&records)
+        }
+        parse::BulkAction::Open => {
+            for file in chosen {
+                let tags = database.get_file_tags(&file.name);
+                let opener =
+                    open::Opener::find(&config.openers, open::Action::Open, &file.path, &tags)?;
+                opener.run()?;
+            }
+            Ok(())
+        }
+        parse::BulkAction::Tag | parse::BulkAction::Untag => {
+            // reuse the settags syntax, a leading = removes the tag
+            let prefix = match action {
+                parse::BulkAction::Untag => "=",
+                _ => "+",
+            };
+            let change: Vec<String> = change_tags
+                .iter()
+                .map(|x| format!("{}{}", prefix, x.trim_start_matches(['+', '='])))
+                .collect();
+            let mut filter = tag_relations::parse_tags(&change)?;
+            let created = ensure_tags(&database, &mut filter, unknown)?;
+            let paths: Vec<PathBuf> = chosen.into_iter().map(|x| x.path).collect();
+            open_database(config)?
+                .with_tags(&created)
+                .set_tags(&paths, filter)
+        }
+        parse::BulkAction::Remove => {
+            let paths: Vec<PathBuf> = chosen.into_iter().map(|x| x.path).collect();
+            open_database(config)?.delete_files(&paths)
+        }
+        parse::BulkAction::Link => add_to_link_directory(config, &chosen),
+    }
+}
+
+fn info(config: &config::Config, output: &Output, file: OsString) -> Result<(), Error> {
+    let database = open_database(config)?;
+    let matches = select::matching(&database, &file);
+
+    let record = if matches.is_empty() {
+        let path = match Path::new(&file).canonicalize().ok() {
+            Some(path) => path,
+            None => {
+                return Err(Error::NotFound(format!(
+                    "'{}' is not a tracked file and does not exist",
+                    style::escape_path(Path::new(&file))
+                )))
+            }
+        };
+        let metadata = fs::metadata(&path).ok();
+        InfoRecord {
+            name: StoredFile::name_of(&path).unwrap_or_default(),
+            path: style::escape_path(&path),
+            raw_path: path.clone(),
+            tracked: false,
+            tags: vec![],
+            tag_children: vec![],
+            last_used: 0,
+            exists: true,
+            size: metadata.as_ref().map(|x| x.len()),
+            modified: metadata.as_ref().and_then(modified_epoch),
+            views: vec![],
+            mode: AddMode::None.name().to_owned(),
+            original: None,
         }
-        Ok(())
     } else {
-        let file = prompt::choose_file(actual_files);
-        if let Err(e) = file {
-            return Err(e.to_string());
+        let file = select::ask(&database, config.picker, matches)?;
+        let tags = database.get_file_tags(&file.name);
+        let metadata = fs::metadata(&file.path).ok();
+        InfoRecord {
+            tracked: true,
+            tags: tags.iter().map(|x| x.name.clone()).collect(),
+            tag_children: tags
+                .iter()
+                .flat_map(|tag| {
+                    tag.children
+                        .iter()
+                        .map(|child| format!("{}/{}", tag.name, child.name))
+                })
+                .collect(),
+            last_used: file.last_used,
+            exists: metadata.is_some(),
+            size: metadata.as_ref().map(|x| x.len()),
+            modified: metadata.as_ref().and_then(modified_epoch),
+            views: find_views(config, &file.path)
+                .iter()
+                .map(|x| style::escape_path(x))
+                .collect(),
+            mode: file.mode.name().to_owned(),
+            original: file.original.as_deref().map(style::escape_path),
+            name: file.name,
+            path: style::escape_path(&file.path),
+            raw_path: file.path,
         }
+    };
+
+    if !output.is_text() {
+        return output.records(&[record]);
+    }
 
-        println!("{}", file.unwrap().path);
-        Ok(())
+    let color = style::stdout_color();
+    let tags = database.get_file_tags(&record.name);
+    let optional = |x: Option<String>| x.unwrap_or("-".to_owned());
+    let rows = vec![
+        vec!["name".to_owned(), record.name.clone()],
+        vec!["path".to_owned(), record.path.clone()],
+        vec![
+            "status".to_owned(),
+            match (record.tracked, record.exists) {
+                (false, _) => "untracked".to_owned(),
+                (true, true) => "tracked".to_owned(),
+                (true, false) => "tracked, missing on disk".to_owned(),
+            },
+        ],
+        vec![
+            "tags".to_owned(),
+            if record.tracked {
+                style::tags(&tags, color)
+            } else {
+                String::new()
+            },
+        ],
+        vec!["tag children".to_owned(), record.tag_children.join(" ")],
+        vec!["last used".to_owned(), style::time(record.last_used)],
+        vec![
+            "size".to_owned(),
+            optional(record.size.map(|x| format!("{} bytes", x))),
+        ],
+        vec![
+            "modified".to_owned(),
+            optional(record.modified.map(style::time)),
+        ],
+        vec!["views".to_owned(), record.views.join(" ")],
+        vec!["added as".to_owned(), record.mode.clone()],
+        vec!["original".to_owned(), optional(record.original.clone())],
+    ];
+    table::print(&[], &rows, false)?;
+    Ok(())
+}
+
+fn modified_epoch(metadata: &fs::Metadata) -> Option<u64> {
+    let modified = metadata.modified().ok()?;
+    modified
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .ok()
+        .map(|x| x.as_secs())
+}
+
+/// all symlinks in the link directory that point to this path
+fn find_views(config: &config::Config, path: &Path) -> Vec<PathBuf> {
+    let mut link_directory = PathBuf::new();
+    link_directory.push(config.clone().managed_directory);
+    link_directory.push(config.clone().link_directory_name);
+
+    let mut result = vec![];
+    let mut directories = vec![link_directory];
+    while let Some(directory) = directories.pop() {
+        let entries = match fs::read_dir(&directory) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let entry_path = entry.path();
+            match fs::read_link(&entry_path) {
+                Ok(target) if target == path => result.push(entry_path),
+                Ok(_) => {}
+                Err(_) if entry_path.is_dir() => directories.push(entry_path),
+                Err(_) => {}
+            }
+        }
     }
+    result
 }
 
-fn list_tags(config: &config::Config) -> Result<(), String> {
-    let entries = Database::open(config.clone().managed_directory)?.list_tags()?;
+fn list_tags(
+    config: &config::Config,
+    output: &Output,
+    query: Option<String>,
+    sort: parse::TagSort,
+    reverse: bool,
+    columns: Vec<parse::TagColumn>,
+    no_header: bool,
+) -> Result<(), Error> {
+    let database = open_database(config)?;
+    let mut entries: Vec<(Tag, usize)> = database
+        .list_tags()?
+        .into_iter()
+        .filter(|x| query.as_ref().is_none_or(|query| x.name.contains(query)))
+        .map(|x| {
+            let count = database.count_tag_files(&x.name);
+            (x, count)
+        })
+        .collect();
+
+    match sort {
+        parse::TagSort::Name => entries.sort_by(|a, b| a.0.name.cmp(&b.0.name)),
+        parse::TagSort::Files => entries.sort_by_key(|x| std::cmp::Reverse(x.1)),
+    }
+    if reverse {
+        entries.reverse();
+    }
 
-    for entry in entries {
-        eprintln!("{:?}", entry);
+    if !output.is_text() {
+        let records: Vec<TagRecord> = entries
+            .iter()
+            .map(|(tag, count)| TagRecord::new(tag, *count))
+            .collect();
+        return output.records(&records);
     }
+
+    let color = style::stdout_color();
+    let rows: Vec<Vec<String>> = entries
+        .iter()
+        .map(|(tag, count)| {
+            columns
+                .iter()
+                .map(|column| match column {
+                    parse::TagColumn::Name => style::tag(tag, color),
+                    parse::TagColumn::Files => count.to_string(),
+                    parse::TagColumn::Children => tag
+                        .children
+                        .iter()
+                        .map(|x| x.name.clone())
+                        .collect::<Vec<String>>()
+                        .join(","),
+                    parse::TagColumn::Description => tag.description.clone().unwrap_or_default(),
+                })
+                .collect()
+        })
+        .collect();
+
+    let header: Vec<&str> = columns
+        .iter()
+        .map(|x| match x {
+            parse::TagColumn::Name => "name",
+            parse::TagColumn::Files => "files",
+            parse::TagColumn::Children => "children",
+            parse::TagColumn::Description => "description",
+        })
+        .collect();
+    table::print(&header, &rows, !no_header)?;
     Ok(())
 }
 
-fn list_files(config: &config::Config) -> Result<(), String> {
-    let entries = Database::open(config.clone().managed_directory)?.list_files()?;
+#[allow(clippy::too_many_arguments)]
+fn list_files(
+    config: &config::Config,
+    output: &Output,
+    query: Option<Vec<String>>,
+    sort: parse::FileSort,
+    reverse: bool,
+    mut columns: Vec<parse::FileColumn>,
+    no_header: bool,
+    all_libraries: bool,
+) -> Result<(), Error> {
+    let filter = tag_relations::parse_tags(&query.unwrap_or_default())?;
+
+    let mut entries = library_files(config, filter, all_libraries)?;
 
-    // TODO
-    for entry in entries {
-        eprintln!("{}", entry);
+    match sort {
+        parse::FileSort::Name => entries.sort_by(|a, b| a.1.name.cmp(&b.1.name)),
+        parse::FileSort::Path => entries.sort_by(|a, b| a.1.path.cmp(&b.1.path)),
+        // most recently used first
+        parse::FileSort::LastUsed => entries.sort_by_key(|x| std::cmp::Reverse(x.1.last_used)),
+        parse::FileSort::Tags => entries.sort_by_key(|x| std::cmp::Reverse(x.2.len())),
+    }
+    if reverse {
+        entries.reverse();
     }
+
+    if !output.is_text() {
+        let records: Vec<FileRecord> = entries
+            .iter()
+            .map(|(library, file, tags)| FileRecord::new(file, tags, &library.name))
+            .collect();
+        return output.records(&records);
+    }
+
+    if all_libraries && !columns.contains(&parse::FileColumn::Library) {
+        columns.insert(0, parse::FileColumn::Library);
+    }
+    let color = style::stdout_color();
+    let rows: Vec<Vec<String>> = entries
+        .iter()
+        .map(|(library, file, tags)| {
+            columns
+                .iter()
+                .map(|column| match column {
+                    parse::FileColumn::Name => file.name.clone(),
+                    parse::FileColumn::Tags => style::tags(tags, color),
+                    parse::FileColumn::Path => style::escape_path(&file.path),
+                    parse::FileColumn::Exists => {
+                        if file.path.exists() {
+                            "yes".to_owned()
+                        } else {
+                            "missing".to_owned()
+                        }
+                    }
+                    parse::FileColumn::LastUsed => style::time(file.last_used),
+                    parse::FileColumn::Library => library.name.clone(),
+                })
+                .collect()
+        })
+        .collect();
+
+    let header: Vec<&str> = columns
+        .iter()
+        .map(|x| match x {
+            parse::FileColumn::Name => "name",
+            parse::FileColumn::Tags => "tags",
+            parse::FileColumn::Path => "path",
+            parse::FileColumn::Exists => "exists",
+            parse::FileColumn::LastUsed => "last used",
+            parse::FileColumn::Library => "library",
+        })
+        .collect();
+    table::print(&header, &rows, !no_header)?;
     Ok(())
 }
 
-fn get_as_link_directory(config: &config::Config, tags: Option<Vec<String>>) -> Result<(), String> {
-    let tags = match tags {
-        Some(list) => list,
-        None => vec![],
-    };
+fn preview(path: PathBuf) -> Result<(), Error> {
+    for line in picker::preview(&path, 200) {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+fn init(shell: shell::Shell, cmd: String) -> Result<(), Error> {
+    print!("{}", shell::init(shell, &cmd)?);
+    Ok(())
+}
+
+fn get_as_link_directory(config: &config::Config, tags: Option<Vec<String>>) -> Result<(), Error> {
+    let tags = tags.unwrap_or_default();
 
     let filter = tag_relations::parse_tags(&tags)?;
 
-    let files = Database::open(config.clone().managed_directory)?.get_files(filter);
-    if let Err(message) = files {
-        return Err(message.to_string());
-    }
+    let files = open_database(config)?.get_files(filter)?;
 
     // create and clear directory
     let mut final_directory_path = PathBuf::new();
@@ -199,53 +2046,54 @@ fn get_as_link_directory(config: &config::Config, tags: Option<Vec<String>>) ->
     final_directory_path.push(config.clone().link_directory_name);
 
     if final_directory_path.exists() {
-        if let Err(message) = fs::remove_dir_all(final_directory_path.clone()) {
-            return Err(message.to_string());
-        }
-    }
-    if let Err(message) = fs::create_dir(final_directory_path) {
-        return Err(message.to_string());
+        fs::remove_dir_all(final_directory_path.clone())?;
     }
+    fs::create_dir(final_directory_path)?;
 
     // do thing
-    let files = match files {
-        Ok(files) => files,
-        Err(message) => return Err(message.to_string()),
-    };
     for file in files {
         let mut final_file_path = PathBuf::new();
         final_file_path.push(config.clone().managed_directory);
         final_file_path.push(config.clone().link_directory_name);
         final_file_path.push(file.name);
-        if let Err(message) = symlink_auto(file.path, final_file_path) {
-            return Err(message.to_string());
-        }
+        symlink_auto(file.path, final_file_path)?;
     }
 
-    return Ok(());
+    Ok(())
 }
 
-fn better_delete(dst: impl AsRef<Path>) -> io::Result<()> {
-    if dst.as_ref().is_file() {
-        return fs::remove_file(dst);
+/// symlinks the files into the link directory, next to what is already in there
+fn add_to_link_directory(config: &config::Config, files: &[StoredFile]) -> Result<(), Error> {
+    let mut directory = PathBuf::new();
+    directory.push(config.clone().managed_directory);
+    directory.push(config.clone().link_directory_name);
+    fs::create_dir_all(&directory)?;
+
+    for file in files {
+        let link = directory.join(&file.name);
+        if fs::symlink_metadata(&link).is_ok() {
+            eprintln!(
+                "'{}' is already in the link directory, skipping it",
+                file.name
+            );
+            continue;
+        }
+        symlink_auto(&file.path, link)?;
     }
-    fs::remove_dir_all(dst)
+    Ok(())
 }
 
-fn better_copy(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> io::Result<u64> {
-    if src.as_ref().is_file() {
-        return fs::copy(src, dst);
-    }
+/// writes the path as it is, also when it is not valid utf-8, so scripts get the real path
+fn print_path(path: &Path) -> Result<(), Error> {
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(path.as_os_str().as_encoded_bytes())?;
+    stdout.write_all(b"\n")?;
+    Ok(())
+}
 
-    fs::create_dir_all(&dst)?;
-    for entry in fs::read_dir(src)? {
-        let entry = entry?;
-        let ty = entry.file_type()?;
-        if ty.is_dir() {
-            better_copy(entry.path(), dst.as_ref().join(entry.file_name()))?;
-        } else {
-            fs::copy(entry.path(), dst.as_ref().join(entry.file_name()))?;
-        }
-    }
-    Ok(0)
+/// seconds since the unix epoch
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |x| x.as_secs())
 }
@@ -2,12 +2,20 @@ use ron::ser::{to_string_pretty, PrettyConfig};
 use serde::{Deserialize, Serialize};
 use std::fs::File;
 use std::io::{BufReader, Read, Write};
-use std::ops::Index;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use super::types::{ChildTag, FileTagConnection, StoredFile, Tag, TagFilter};
+use crate::error::Error;
 
-#[derive(Debug, Deserialize, Serialize)]
+use super::exchange::{self, Export, ExportedFile, Merge};
+use super::journal::{self, Change, Entry, FileMove, Item, Journal};
+use super::sidecar::{self, Sidecar};
+use super::transfer;
+use super::types::{
+    escape_os_str, ChildTag, FileTagConnection, StoredFile, Tag, TagColor, TagFilter,
+};
+use super::xdg_tags;
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
 struct Data {
     files: Vec<StoredFile>,
     connections: Vec<FileTagConnection>,
@@ -15,104 +23,446 @@ struct Data {
     child_tags: Vec<ChildTag>,
 }
 
+/// how a write shows up in the journal
+enum Record {
+    /// a new change that can be undone
+    New,
+    /// not worth undoing, like the last used time
+    Skip,
+    /// an undo or redo, only the position in the journal changes
+    Position(usize),
+}
+
+/// where the tags of files are written besides the database, so other programs can see them
+#[derive(Clone, Copy, Default)]
+pub struct Mirror {
+    /// the `user.xdg.tags` extended attribute
+    pub xattr: bool,
+    /// a `.tagged.ron` in every directory with tagged files
+    pub sidecar: bool,
+}
+
 pub struct Database {
     data: Data,
+    /// the data as it was read, to find out what changed
+    original: Data,
+    /// the file as it was read, to notice when another process wrote it since
+    text: String,
     location: PathBuf,
+    moves: Vec<FileMove>,
+    mirror: Mirror,
 }
 impl Database {
-    fn get_or_create_file(path: &PathBuf) -> Result<File, String> {
-        let file_result = File::open(path);
-        let file: File;
-        if let Err(e) = file_result {
-            if e.kind() != std::io::ErrorKind::NotFound {
-                return Err(e.to_string());
+    fn get_or_create_file(path: &PathBuf) -> Result<File, Error> {
+        match File::open(path) {
+            Ok(file) => return Ok(file),
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(Error::Io(format!(
+                    "could not open {}, {}",
+                    path.display(),
+                    e
+                )))
+            }
+            Err(_) => {}
+        }
+
+        eprintln!("Creating a new database file at {:?}", &path); // TODO i dont really want printlines littered around
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        Database::create_file(path)?;
+
+        Ok(File::open(path)?)
+    }
+
+    fn create_file(path: &PathBuf) -> Result<(), Error> {
+        let mut file = File::create(path)?;
+
+        let default_data = Database::serialize(&Data {
+            files: vec![],
+            connections: vec![],
+            tags: vec![],
+            child_tags: vec![],
+        })?;
+        file.write_all(default_data.as_bytes())?;
+
+        // it is no use returning this file because it is in write-only mode
+        Ok(())
+    }
+
+    fn serialize(data: &Data) -> Result<String, Error> {
+        to_string_pretty(data, PrettyConfig::new().indentor("  ".to_owned()))
+            .map_err(|e| Error::Parse(format!("could not serialize the database, {}", e)))
+    }
+
+    /// writes the changes and records them in the journal, so they can be undone
+    fn apply(self) -> Result<(), Error> {
+        self.save(Record::New)
+    }
+
+    /// writes the data to a temporary file and renames it over the database, so a crash never leaves half a file
+    ///
+    /// the lock file keeps two processes from writing at once, and when another process wrote the database since it
+    /// was read here this fails instead of overwriting its changes
+    fn save(self, record: Record) -> Result<(), Error> {
+        let new_data = Database::serialize(&self.data)?;
+        let mirror = !matches!(record, Record::Skip);
+
+        let lock_path = self.location.with_extension("ron.lock");
+        let lock = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&lock_path);
+        if let Err(e) = lock {
+            if e.kind() == std::io::ErrorKind::AlreadyExists {
+                return Err(Error::Lock(format!(
+                    "the database is being written by another process, remove {} if that is not the case",
+                    lock_path.display()
+                )));
             }
+            return Err(e.into());
+        }
 
-            eprintln!("Creating a new database file at {:?}", &path); // TODO i dont really want printlines littered around
+        // the data first, a journal entry for changes that were never written would undo something else
+        let result = self
+            .check_unchanged()
+            .and_then(|_| {
+                let temp_path = self.location.with_extension("ron.tmp");
+                let written = File::create(&temp_path)
+                    .and_then(|mut file| file.write_all(new_data.as_bytes()))
+                    .and_then(|_| std::fs::rename(&temp_path, &self.location));
+                if let Err(e) = written {
+                    return Err(Error::Io(format!(
+                        "could not write {}, {}",
+                        self.location.display(),
+                        e
+                    )));
+                }
+                Ok(())
+            })
+            .and_then(|_| self.write_journal(record));
+        let _ = std::fs::remove_file(&lock_path);
 
-            Database::create_file(path)?;
+        if result.is_ok() && mirror {
+            self.mirror_tags();
+        }
+        result
+    }
 
-            let file_result = File::open(path);
-            if let Err(e) = file_result {
-                return Err(e.to_string());
+    /// fails when the file is not what was read anymore, only called while the lock is held
+    fn check_unchanged(&self) -> Result<(), Error> {
+        let text = match std::fs::read_to_string(&self.location) {
+            Ok(text) => text,
+            Err(e) => {
+                return Err(Error::Io(format!(
+                    "could not read {}, {}",
+                    self.location.display(),
+                    e
+                )))
             }
-            file = file_result.unwrap();
-        } else {
-            file = file_result.unwrap();
+        };
+        if text != self.text {
+            return Err(Error::Lock(format!(
+                "{} was changed by another process while this ran, nothing was written, try again",
+                self.location.display()
+            )));
         }
+        Ok(())
+    }
 
-        return Ok(file);
+    /// `journal.ron` next to `data.ron`, other database files get their own journal like `work.journal.ron`
+    fn journal_path(&self) -> PathBuf {
+        if self.location.file_name().is_some_and(|x| x == "data.ron") {
+            return self.location.with_file_name("journal.ron");
+        }
+        self.location.with_extension("journal.ron")
     }
 
-    fn create_file(path: &PathBuf) -> Result<(), String> {
-        let file_result = File::create(path);
-        if let Err(e) = file_result {
-            return Err(e.to_string());
+    fn write_journal(&self, record: Record) -> Result<(), Error> {
+        let mut journal = match record {
+            Record::Skip => return Ok(()),
+            _ => Journal::read(&self.journal_path())?,
+        };
+
+        match record {
+            Record::New => {
+                let changes = self.changes();
+                if changes.is_empty() && self.moves.is_empty() {
+                    return Ok(());
+                }
+                // only the name of the binary, the full path is just noise
+                let command = std::env::args_os()
+                    .enumerate()
+                    .map(|(i, x)| match Path::new(&x).file_name() {
+                        Some(name) if i == 0 => escape_os_str(name),
+                        _ => escape_os_str(&x),
+                    })
+                    .collect::<Vec<String>>();
+                journal.push(Entry {
+                    time: crate::now(),
+                    command: shell_words::join(command),
+                    changes,
+                    moves: self.moves.clone(),
+                });
+            }
+            Record::Position(position) => journal.position = position,
+            Record::Skip => {}
         }
-        let mut file = file_result.unwrap();
+        journal.write(&self.journal_path())
+    }
+
+    /// what changed since the database was opened
+    fn changes(&self) -> Vec<Change> {
+        let before = &self.original;
+        let after = &self.data;
+        let mut changes = journal::diff(&before.files, &after.files, Item::File);
+        changes.extend(journal::diff(
+            &before.connections,
+            &after.connections,
+            Item::Connection,
+        ));
+        changes.extend(journal::diff(&before.tags, &after.tags, Item::Tag));
+        changes.extend(journal::diff(
+            &before.child_tags,
+            &after.child_tags,
+            Item::ChildTag,
+        ));
+        changes
+    }
 
-        let default_data = to_string_pretty(
-            &Data {
-                files: vec![],
-                connections: vec![],
-                tags: vec![],
-                child_tags: vec![],
+    fn replay(&mut self, changes: &[Change]) {
+        journal::apply(
+            &mut self.data.files,
+            changes,
+            |x| match x {
+                Item::File(file) => Some(file),
+                _ => None,
             },
-            PrettyConfig::new().indentor("  ".to_owned()),
-        )
-        .unwrap();
+            |a, b| a.path == b.path,
+        );
+        journal::apply(
+            &mut self.data.connections,
+            changes,
+            |x| match x {
+                Item::Connection(connection) => Some(connection),
+                _ => None,
+            },
+            |a, b| a == b,
+        );
+        journal::apply(
+            &mut self.data.tags,
+            changes,
+            |x| match x {
+                Item::Tag(tag) => Some(tag),
+                _ => None,
+            },
+            |a, b| a.name == b.name,
+        );
+        journal::apply(
+            &mut self.data.child_tags,
+            changes,
+            |x| match x {
+                Item::ChildTag(child) => Some(child),
+                _ => None,
+            },
+            |a, b| a == b,
+        );
+    }
 
-        if let Err(e) = file.write_all(default_data.as_bytes()) {
-            return Err(e.to_string());
+    /// reverts the last change that is not undone yet, and moves files back, returns what was undone
+    pub fn undo(mut self) -> Result<Option<Entry>, Error> {
+        let journal = Journal::read(&self.journal_path())?;
+        if journal.position == 0 {
+            return Ok(None);
         }
+        let entry = journal.entries[journal.position - 1].clone();
 
-        // it is no use returning this file because it is in write-only mode
-        return Ok(());
+        // files that were already moved back go where they were again when something fails
+        for (i, file_move) in entry.moves.iter().enumerate().rev() {
+            if let Err(e) = journal::undo_move(file_move) {
+                redo_moves(&entry.moves[i + 1..]);
+                return Err(e);
+            }
+        }
+        self.replay(&journal::invert(&entry.changes));
+        if let Err(e) = self.save(Record::Position(journal.position - 1)) {
+            redo_moves(&entry.moves);
+            return Err(e);
+        }
+        Ok(Some(entry))
     }
 
-    fn apply(self) -> Result<(), String> {
-        let file_result = File::create(&self.location); //create really is just open in write-only mode
-        if let Err(e) = file_result {
-            return Err(e.to_string());
+    /// does the last undone change again, returns what was redone
+    pub fn redo(mut self) -> Result<Option<Entry>, Error> {
+        let journal = Journal::read(&self.journal_path())?;
+        let entry = match journal.entries.get(journal.position) {
+            Some(entry) => entry.clone(),
+            None => return Ok(None),
+        };
+
+        for (i, file_move) in entry.moves.iter().enumerate() {
+            if let Err(e) = journal::redo_move(file_move) {
+                undo_moves(&entry.moves[..i]);
+                return Err(e);
+            }
+        }
+        self.replay(&entry.changes);
+        if let Err(e) = self.save(Record::Position(journal.position + 1)) {
+            undo_moves(&entry.moves);
+            return Err(e);
+        }
+        Ok(Some(entry))
+    }
+
+    /// writes the tags of the files whose tags changed to the mirrors, files that were removed lose their tags there
+    ///
+    /// the database is already written, so this only warns when it fails
+    fn mirror_tags(&self) {
+        if !self.mirror.xattr && !self.mirror.sidecar {
+            return;
+        }
+
+        // the path, and the tags it had before and has now
+        let mut changed: Vec<(&Path, Vec<&str>, Vec<&str>)> = vec![];
+        for file in &self.data.files {
+            let tags = sorted_tags(&self.data, &file.name);
+            let before = match self.original.files.iter().find(|x| x.path == file.path) {
+                Some(before) => sorted_tags(&self.original, &before.name),
+                None => vec![],
+            };
+            if before != tags {
+                changed.push((&file.path, before, tags));
+            }
+        }
+        for file in &self.original.files {
+            if !self.data.files.iter().any(|x| x.path == file.path) {
+                changed.push((&file.path, sorted_tags(&self.original, &file.name), vec![]));
+            }
         }
-        let mut file = file_result.unwrap();
 
-        let new_data =
-            to_string_pretty(&self.data, PrettyConfig::new().indentor("  ".to_owned())).unwrap();
+        if self.mirror.xattr {
+            mirror_xattr(&changed);
+        }
+        if self.mirror.sidecar {
+            let mut directories: Vec<&Path> = vec![];
+            for directory in changed.iter().filter_map(|x| x.0.parent()) {
+                if !directories.contains(&directory) {
+                    directories.push(directory);
+                }
+            }
+            let failed = self.write_sidecars(&directories);
+            if let Some((path, e)) = failed.first() {
+                eprintln!(
+                    "could not write {} sidecars, e.g. in {}, {}",
+                    failed.len(),
+                    path.display(),
+                    e
+                );
+            }
+        }
+    }
 
-        if let Err(e) = file.write_all(new_data.as_bytes()) {
-            return Err(e.to_string());
+    /// the sidecar of a directory as the database has it
+    pub fn sidecar(&self, directory: &Path) -> Sidecar {
+        let mut sidecar = Sidecar::default();
+        for file in &self.data.files {
+            if file.path.parent() != Some(directory) || sidecar::is_sidecar(&file.path) {
+                continue;
+            }
+            // a sidecar is text, names that are not valid utf-8 cant be in it
+            let name = match file.path.file_name().and_then(|x| x.to_str()) {
+                Some(name) => name,
+                None => continue,
+            };
+            let tags = self.file_tag_names(&file.name);
+            if !tags.is_empty() {
+                sidecar.files.insert(name.to_owned(), tags);
+            }
+        }
+        sidecar
+    }
+
+    /// writes the sidecars of these directories, directories that are gone are skipped
+    ///
+    /// returns the directories where it failed
+    pub fn write_sidecars(&self, directories: &[&Path]) -> Vec<(PathBuf, std::io::Error)> {
+        let mut failed = vec![];
+        for directory in directories {
+            if !directory.is_dir() {
+                continue;
+            }
+            let sidecar = self.sidecar(directory);
+            if sidecar::read(directory).ok().flatten().unwrap_or_default() == sidecar {
+                continue;
+            }
+            if let Err(e) = sidecar::write(directory, &sidecar) {
+                failed.push((directory.to_path_buf(), e));
+            }
         }
-        return Ok(());
+        failed
+    }
+
+    /// every change in the journal, and the position before which they are done
+    pub fn history(&self) -> Result<Journal, Error> {
+        Journal::read(&self.journal_path())
+    }
+
+    /// files that were moved on disk before they are added, so the move is undone together with the change
+    pub fn with_moves(mut self, moves: Vec<FileMove>) -> Database {
+        self.moves = moves;
+        self
+    }
+
+    /// creates the tags that do not exist yet, they are written together with the change that uses them
+    pub fn with_tags(mut self, names: &[String]) -> Database {
+        self.create_tags(names);
+        self
     }
 
-    pub fn open(mut path: PathBuf) -> Result<Database, String> {
-        path.push("data.ron"); // TODO this should come from config
+    /// also writes the tags of files that change to these places
+    pub fn with_mirror(mut self, mirror: Mirror) -> Database {
+        self.mirror = mirror;
+        self
+    }
+
+    /// opens the database file, and creates it if it does not exist
+    pub fn open(path: PathBuf) -> Result<Database, Error> {
         let file = Database::get_or_create_file(&path)?;
 
         let mut output = String::new();
         let mut buf_reader = BufReader::new(file);
-        if let Err(e) = buf_reader.read_to_string(&mut output) {
-            return Err(e.to_string());
-        }
-        let data: Data = ron::from_str(&output).unwrap();
+        buf_reader.read_to_string(&mut output)?;
+        let data: Data = match ron::from_str(&output) {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(Error::Parse(format!(
+                    "could not read {}, {}",
+                    path.display(),
+                    e
+                )))
+            }
+        };
 
-        return Ok(Database {
+        Ok(Database {
+            original: data.clone(),
             data,
+            text: output,
             location: path,
-        });
+            moves: vec![],
+            mirror: Mirror::default(),
+        })
     }
 
-    pub fn list_files(self) -> Result<Vec<StoredFile>, String> {
-        return Ok(self.data.files);
+    pub fn list_files(&self) -> Vec<StoredFile> {
+        self.data.files.clone()
     }
 
     // TODO update for child tags
-    pub fn list_tags(self) -> Result<Vec<Tag>, String> {
-        return Ok(self.data.tags);
+    pub fn list_tags(&self) -> Result<Vec<Tag>, Error> {
+        Ok(self.data.tags.clone())
     }
 
-    pub fn get_files(self, filter: TagFilter) -> Result<Vec<StoredFile>, String> {
+    pub fn get_files(&self, filter: TagFilter) -> Result<Vec<StoredFile>, Error> {
         let mut result = self.data.files.clone();
         if filter.is_empty() {
             return Ok(result);
@@ -126,54 +476,216 @@ impl Database {
         let file_names: Vec<String> = connections.iter().map(|x| x.file_name.clone()).collect();
         result.retain(|x| file_names.contains(&x.name));
 
-        return Ok(result);
+        Ok(result)
     }
 
-    pub fn add_tag(mut self, name: String) -> Result<(), String> {
-        let new_tag = Tag {
-            name,
-            children: vec![],
-        };
-        self.data.tags.push(new_tag);
+    /// adds all the tags, or none of them if one already exists
+    pub fn add_tags(mut self, names: Vec<String>) -> Result<(), Error> {
+        for name in names {
+            if self.data.tags.iter().any(|x| x.name == name) {
+                return Err(Error::Invalid(format!("the tag '{}' already exists", name)));
+            }
+            self.data.tags.push(Tag::new(name));
+        }
 
         self.apply()?;
-        return Ok(());
+        Ok(())
     }
 
-    pub fn add_file(mut self, name: String, path: String, last_used: u64) -> Result<(), String> {
-        let new_tag = StoredFile {
-            name,
-            path,
-            last_used,
+    /// changes the metadata of a tag, `None` leaves a field as it is and an empty string clears it
+    pub fn edit_tag(
+        mut self,
+        name: String,
+        description: Option<String>,
+        color: Option<String>,
+        icon: Option<String>,
+    ) -> Result<(), Error> {
+        let tag = match self.data.tags.iter_mut().find(|x| x.name == name) {
+            Some(tag) => tag,
+            None => {
+                return Err(Error::NotFound(format!(
+                    "Couldnt find tag with name: {}",
+                    name
+                )))
+            }
         };
-        self.data.files.push(new_tag);
+
+        if let Some(description) = description {
+            tag.description = Some(description).filter(|x| !x.is_empty());
+        }
+        if let Some(color) = color {
+            tag.color = if color.is_empty() {
+                None
+            } else {
+                Some(color.parse::<TagColor>().map_err(Error::Invalid)?)
+            };
+        }
+        if let Some(icon) = icon {
+            tag.icon = Some(icon).filter(|x| !x.is_empty());
+        }
 
         self.apply()?;
-        return Ok(());
+        Ok(())
     }
 
-    /// adds allowed tags, and removes denied tags from the given file
-    pub fn set_tags(mut self, file_name: String, mut tag_filter: TagFilter) -> Result<(), String> {
-        let mut removable_tags: Vec<String> = vec![];
-        for (i, tag) in tag_filter.allowed_tags.iter_mut().enumerate() {
-            if self.data.tags.iter().filter(|x| x.name == *tag).count() == 0 {
-                // TODO should offer to create it as well
-                eprintln!("the tag '{}' does not exist, skipping it", tag);
+    /// all tags connected to a file
+    pub fn get_file_tags(&self, file_name: &str) -> Vec<Tag> {
+        let tag_names: Vec<&String> = self
+            .data
+            .connections
+            .iter()
+            .filter(|x| x.file_name == file_name)
+            .map(|x| &x.tag_name)
+            .collect();
+
+        self.data
+            .tags
+            .iter()
+            .filter(|x| tag_names.contains(&&x.name))
+            .cloned()
+            .collect()
+    }
+
+    /// the names of the tags of a file, in the order the file got them
+    pub fn file_tag_names(&self, file_name: &str) -> Vec<String> {
+        self.data
+            .connections
+            .iter()
+            .filter(|x| x.file_name == file_name)
+            .map(|x| x.tag_name.clone())
+            .collect()
+    }
+
+    /// how many files have this tag
+    pub fn count_tag_files(&self, tag_name: &str) -> usize {
+        self.data
+            .connections
+            .iter()
+            .filter(|x| x.tag_name == tag_name)
+            .count()
+    }
+
+    /// adds the files and gives them the tags, files with a path that is already stored are skipped
+    ///
+    /// every file gets a name no other file has, see `unique_name`
+    ///
+    /// the entries of the `replaced` paths are removed first, the file there was overwritten by a new one
+    ///
+    /// returns the paths that were skipped
+    pub fn add_files(
+        mut self,
+        files: Vec<StoredFile>,
+        tags: &[String],
+        replaced: &[PathBuf],
+    ) -> Result<Vec<PathBuf>, Error> {
+        if let Some(tag) = self.missing_tags(tags).first() {
+            return Err(Error::NotFound(format!("the tag '{}' does not exist", tag)));
+        }
+
+        for path in replaced {
+            self.remove_entry(path);
+        }
+
+        let mut skipped = vec![];
+        for mut file in files {
+            if self.data.files.iter().any(|x| x.path == file.path) {
+                skipped.push(file.path);
+                continue;
+            }
+
+            file.name = self.unique_name(&file.name);
+            for tag in tags {
+                self.connect(&file.name, tag);
             }
+            self.data.files.push(file);
         }
 
-        for tag in tag_filter.allowed_tags {
-            // TODO dont do duplicates
-            self.data.connections.push(FileTagConnection {
-                file_name: file_name.clone(),
-                tag_name: tag,
-            });
+        self.apply()?;
+        Ok(skipped)
+    }
+
+    /// adds the connection if the file does not have the tag yet
+    fn connect(&mut self, file_name: &str, tag_name: &str) {
+        if self
+            .data
+            .connections
+            .iter()
+            .any(|x| x.file_name == file_name && x.tag_name == tag_name)
+        {
+            return;
         }
+        self.data.connections.push(FileTagConnection {
+            file_name: file_name.to_owned(),
+            tag_name: tag_name.to_owned(),
+        });
+    }
 
-        for tag in tag_filter.denied_tags {
-            self.data
-                .connections
-                .retain(|x| !(x.file_name == file_name && x.tag_name == tag));
+    /// stores the new paths of files that were moved on disk, the moves are recorded so `undo` moves them back
+    pub fn move_files(mut self, moves: Vec<FileMove>) -> Result<(), Error> {
+        for file_move in &moves {
+            for file in self
+                .data
+                .files
+                .iter_mut()
+                .filter(|x| x.path == file_move.from)
+            {
+                file.path = file_move.to.clone();
+            }
+        }
+
+        self.moves = moves;
+        self.apply()?;
+        Ok(())
+    }
+
+    /// updates the last used time and use count of a file, these decide the order in the prompt
+    pub fn mark_used(mut self, path: &Path, now: u64) -> Result<(), Error> {
+        for file in self.data.files.iter_mut().filter(|x| x.path == path) {
+            file.last_used = now;
+            file.use_count += 1;
+        }
+
+        self.save(Record::Skip)?;
+        Ok(())
+    }
+
+    /// the tags in this list that are not in the database
+    pub fn missing_tags(&self, names: &[String]) -> Vec<String> {
+        names
+            .iter()
+            .filter(|name| !self.data.tags.iter().any(|x| &x.name == *name))
+            .cloned()
+            .collect()
+    }
+
+    /// adds allowed tags, and removes denied tags from the files with these paths
+    ///
+    /// fails without changing anything if an allowed tag does not exist, see `missing_tags`, or a path is not stored
+    pub fn set_tags(mut self, paths: &[PathBuf], tag_filter: TagFilter) -> Result<(), Error> {
+        if let Some(tag) = self.missing_tags(&tag_filter.allowed_tags).first() {
+            return Err(Error::NotFound(format!("the tag '{}' does not exist", tag)));
+        }
+
+        for path in paths {
+            let file_name = match self.data.files.iter().find(|x| &x.path == path) {
+                Some(file) => file.name.clone(),
+                None => {
+                    return Err(Error::NotFound(format!(
+                        "Couldnt find file with path: {}",
+                        path.display()
+                    )))
+                }
+            };
+
+            for tag in &tag_filter.allowed_tags {
+                self.connect(&file_name, tag);
+            }
+
+            for tag in &tag_filter.denied_tags {
+                self.data
+                    .connections
+                    .retain(|x| !(x.file_name == file_name && &x.tag_name == tag));
+            }
         }
 
         // let existing = &self.conn.connections[&ron::Value::String(file_name)];
@@ -181,28 +693,735 @@ impl Database {
         // let test: Result<Vec<String>, ron::Error> = existing.to_owned().into_rust();
         // self.conn.connections.insert(file_name);
         self.apply()?;
-        return Ok(());
+        Ok(())
     }
 
-    pub fn delete_tag(mut self, tag_name: String) -> Result<(), String> {
-        // TODO remove connections
-        let index = self.data.tags.iter().position(|x| x.name == tag_name);
-        if let Some(i) = index {
-            self.data.tags.remove(i);
-            return Ok(());
-        } else {
-            return Err(format!("Couldnt find tag with name: {}", tag_name));
+    /// removes all the tags, or none of them if one does not exist
+    pub fn delete_tags(mut self, tag_names: Vec<String>) -> Result<(), Error> {
+        for tag_name in tag_names {
+            let index = self.data.tags.iter().position(|x| x.name == tag_name);
+            if let Some(i) = index {
+                self.data.tags.remove(i);
+                self.data.connections.retain(|x| x.tag_name != tag_name);
+            } else {
+                return Err(Error::NotFound(format!(
+                    "Couldnt find tag with name: {}",
+                    tag_name
+                )));
+            }
         }
+
+        self.apply()?;
+        Ok(())
     }
 
-    pub fn delete_file(mut self, file_name: String) -> Result<(), String> {
-        // TODO remove connections
-        let index = self.data.files.iter().position(|x| x.name == file_name);
-        if let Some(i) = index {
-            self.data.files.remove(i);
-            return Ok(());
-        } else {
-            return Err(format!("Couldnt find file with name: {}", file_name));
+    /// removes the files with these paths and their tags, or none of them if one is not stored
+    pub fn delete_files(mut self, paths: &[PathBuf]) -> Result<(), Error> {
+        for path in paths {
+            if self.remove_entry(path).is_none() {
+                return Err(Error::NotFound(format!(
+                    "Couldnt find file with path: {}",
+                    path.display()
+                )));
+            }
         }
+
+        self.apply()?;
+        Ok(())
+    }
+
+    /// removes the stored file with this path and its tags
+    fn remove_entry(&mut self, path: &Path) -> Option<StoredFile> {
+        let index = self.data.files.iter().position(|x| x.path == path)?;
+        let removed = self.data.files.remove(index);
+        self.data
+            .connections
+            .retain(|x| x.file_name != removed.name);
+        Some(removed)
+    }
+
+    /// everything in the database, with the tags of every file
+    pub fn export(&self) -> Export {
+        let files = self
+            .data
+            .files
+            .iter()
+            .map(|file| ExportedFile {
+                name: file.name.clone(),
+                path: file.path.clone(),
+                tags: self.file_tag_names(&file.name),
+                last_used: file.last_used,
+                use_count: file.use_count,
+                mode: file.mode,
+                original: file.original.clone(),
+            })
+            .collect();
+
+        Export {
+            version: exchange::VERSION,
+            files,
+            tags: self.data.tags.clone(),
+        }
+    }
+
+    /// merges an export into the database, see `Export::validate` for what has to be checked first
+    ///
+    /// with `dry_run` nothing is written, the report says what would change
+    pub fn import(
+        mut self,
+        incoming: Export,
+        merge: Merge,
+        dry_run: bool,
+    ) -> Result<ImportReport, Error> {
+        let mut report = ImportReport::default();
+        let prefer_incoming = merge == Merge::PreferIncoming;
+
+        if merge == Merge::Replace {
+            self.data.files.clear();
+            self.data.connections.clear();
+            self.data.tags.clear();
+        }
+
+        for tag in incoming.tags {
+            let existing = match self.data.tags.iter_mut().find(|x| x.name == tag.name) {
+                Some(existing) => existing,
+                None => {
+                    self.data.tags.push(tag);
+                    continue;
+                }
+            };
+            let subject = format!("tag {}", tag.name);
+            let conflicts = &mut report.conflicts;
+            merge_value(
+                &mut existing.description,
+                tag.description,
+                prefer_incoming,
+                (&subject, "description"),
+                |x| x.clone(),
+                conflicts,
+            );
+            merge_value(
+                &mut existing.color,
+                tag.color,
+                prefer_incoming,
+                (&subject, "color"),
+                |x| x.name().to_owned(),
+                conflicts,
+            );
+            merge_value(
+                &mut existing.icon,
+                tag.icon,
+                prefer_incoming,
+                (&subject, "icon"),
+                |x| x.clone(),
+                conflicts,
+            );
+            for child in tag.children {
+                if !existing.children.contains(&child) {
+                    existing.children.push(child);
+                }
+            }
+        }
+
+        for file in incoming.files {
+            if std::fs::symlink_metadata(&file.path).is_err() {
+                report.missing_files.push(file.path.clone());
+            }
+            // csv has no tag list, and a json file could have been edited by hand
+            for tag in &file.tags {
+                if !self.data.tags.iter().any(|x| &x.name == tag) {
+                    self.data.tags.push(Tag::new(tag.clone()));
+                }
+            }
+
+            let index = match self.data.files.iter().position(|x| x.path == file.path) {
+                Some(index) => index,
+                None => {
+                    let name = self.unique_name(&file.name);
+                    if name != file.name {
+                        report.renamed_files.push((file.path.clone(), name.clone()));
+                    }
+                    for tag in &file.tags {
+                        self.connect(&name, tag);
+                    }
+                    self.data.files.push(StoredFile {
+                        name,
+                        path: file.path,
+                        last_used: file.last_used,
+                        use_count: file.use_count,
+                        mode: file.mode,
+                        original: file.original,
+                    });
+                    continue;
+                }
+            };
+
+            let subject = path_text(&file.path);
+            let existing = &mut self.data.files[index];
+            // these are only counters, the higher one is the more useful one
+            existing.last_used = existing.last_used.max(file.last_used);
+            existing.use_count = existing.use_count.max(file.use_count);
+
+            let mut mode = Some(existing.mode);
+            merge_value(
+                &mut mode,
+                Some(file.mode),
+                prefer_incoming,
+                (&subject, "mode"),
+                |x| x.name().to_owned(),
+                &mut report.conflicts,
+            );
+            existing.mode = mode.unwrap_or_default();
+            merge_value(
+                &mut existing.original,
+                file.original,
+                prefer_incoming,
+                (&subject, "original"),
+                |x| path_text(x),
+                &mut report.conflicts,
+            );
+
+            let mut name = Some(existing.name.clone());
+            merge_value(
+                &mut name,
+                Some(file.name),
+                prefer_incoming,
+                (&subject, "name"),
+                |x| x.clone(),
+                &mut report.conflicts,
+            );
+            let mut name = name.unwrap_or_default();
+            if name != existing.name {
+                let path = existing.path.clone();
+                if self
+                    .data
+                    .files
+                    .iter()
+                    .any(|x| x.name == name && x.path != path)
+                {
+                    name = self.unique_name(&name);
+                    report.renamed_files.push((path, name.clone()));
+                }
+                let existing = &mut self.data.files[index];
+                let old = std::mem::replace(&mut existing.name, name.clone());
+                self.rename_connections(&old, &name);
+            }
+
+            let here = self.file_tag_names(&name);
+            if prefer_incoming && here.iter().any(|x| !file.tags.contains(x)) {
+                report.conflicts.push(ImportConflict {
+                    subject: subject.clone(),
+                    field: "tags",
+                    here: here.join(" "),
+                    incoming: file.tags.join(" "),
+                });
+                self.data
+                    .connections
+                    .retain(|x| x.file_name != name || file.tags.contains(&x.tag_name));
+            }
+            for tag in &file.tags {
+                self.connect(&name, tag);
+            }
+        }
+
+        report.count(&self.original, &self.data);
+        if !dry_run {
+            self.apply()?;
+        }
+        Ok(report)
+    }
+
+    /// gives stored files exactly these tags, and adds the new files with theirs, tags that do not exist yet are created
+    ///
+    /// new files get a name no other file has, so they dont share the tags of a file somewhere else
+    pub fn sync_tags(
+        mut self,
+        files: Vec<(StoredFile, Vec<String>)>,
+        file_tags: Vec<(String, Vec<String>)>,
+    ) -> Result<(), Error> {
+        for (file_name, tags) in file_tags {
+            self.create_tags(&tags);
+            self.data
+                .connections
+                .retain(|x| x.file_name != file_name || tags.contains(&x.tag_name));
+            for tag in &tags {
+                self.connect(&file_name, tag);
+            }
+        }
+
+        for (mut file, tags) in files {
+            if self.data.files.iter().any(|x| x.path == file.path) {
+                continue;
+            }
+            file.name = self.unique_name(&file.name);
+            self.create_tags(&tags);
+            for tag in &tags {
+                self.connect(&file.name, tag);
+            }
+            self.data.files.push(file);
+        }
+
+        self.apply()?;
+        Ok(())
+    }
+
+    fn create_tags(&mut self, names: &[String]) {
+        for name in names {
+            if !self.data.tags.iter().any(|x| &x.name == name) {
+                self.data.tags.push(Tag::new(name.clone()));
+            }
+        }
+    }
+
+    /// the name, or `name (1).ext` and so on if another file has it, connections go by name so the files would share their tags
+    fn unique_name(&self, name: &str) -> String {
+        let mut unique = name.to_owned();
+        let mut i = 1;
+        while self.data.files.iter().any(|x| x.name == unique) {
+            unique = transfer::numbered_name(Path::new(name), i);
+            i += 1;
+        }
+        unique
+    }
+
+    /// gives the tags of a file to its new name
+    fn rename_connections(&mut self, old: &str, new: &str) {
+        let tags = self.file_tag_names(old);
+        self.data.connections.retain(|x| x.file_name != old);
+        for tag in tags {
+            self.connect(new, &tag);
+        }
+    }
+
+    /// merges files stored more than once under the same canonical path, and removes repeated connections and tags
+    ///
+    /// different files that share a name get a name of their own, and keep the tags they shared
+    ///
+    /// with `dry_run` nothing is written, the report says what would change
+    pub fn dedupe(mut self, dry_run: bool) -> Result<DedupeReport, Error> {
+        let mut report = DedupeReport::default();
+
+        // every kept file, with the names of the entries that were merged into it
+        let mut files: Vec<(StoredFile, Vec<String>)> = vec![];
+        for mut file in std::mem::take(&mut self.data.files) {
+            // paths that are gone can not be canonicalized, they are compared as they are
+            if let Ok(canonical) = std::fs::canonicalize(&file.path) {
+                if canonical != file.path {
+                    report.canonical_paths += 1;
+                    file.path = canonical;
+                }
+            }
+
+            match files.iter_mut().find(|x| x.0.path == file.path) {
+                Some((kept, names)) => {
+                    kept.last_used = kept.last_used.max(file.last_used);
+                    kept.use_count += file.use_count;
+                    if !names.contains(&file.name) {
+                        names.push(file.name.clone());
+                    }
+                    match report.merged_files.iter_mut().find(|x| x.0 == file.path) {
+                        Some(merged) => merged.1 += 1,
+                        None => report.merged_files.push((file.path.clone(), 2)),
+                    }
+                }
+                None => {
+                    let names = vec![file.name.clone()];
+                    files.push((file, names));
+                }
+            }
+        }
+
+        // a file gets the tags of all its names, connections that belong to no file stay as they are
+        let old_connections = std::mem::take(&mut self.data.connections);
+        for connection in &old_connections {
+            if !files.iter().any(|x| x.1.contains(&connection.file_name)) {
+                self.data.connections.push(connection.clone());
+            }
+        }
+        for (mut file, names) in files {
+            let name = self.unique_name(&file.name);
+            if name != file.name {
+                report.renamed_files.push((file.path.clone(), name.clone()));
+                file.name = name;
+            }
+            for connection in old_connections
+                .iter()
+                .filter(|x| names.contains(&x.file_name))
+            {
+                self.data.connections.push(FileTagConnection {
+                    file_name: file.name.clone(),
+                    tag_name: connection.tag_name.clone(),
+                });
+            }
+            self.data.files.push(file);
+        }
+
+        let mut connections: Vec<FileTagConnection> = vec![];
+        for connection in std::mem::take(&mut self.data.connections) {
+            if connections
+                .iter()
+                .any(|x| x.file_name == connection.file_name && x.tag_name == connection.tag_name)
+            {
+                report.removed_connections += 1;
+            } else {
+                connections.push(connection);
+            }
+        }
+        self.data.connections = connections;
+
+        // the first one wins where both have a value, children are combined
+        let mut tags: Vec<Tag> = vec![];
+        for tag in std::mem::take(&mut self.data.tags) {
+            let kept = match tags.iter_mut().find(|x| x.name == tag.name) {
+                Some(kept) => kept,
+                None => {
+                    tags.push(tag);
+                    continue;
+                }
+            };
+            kept.description = kept.description.take().or(tag.description);
+            kept.color = kept.color.take().or(tag.color);
+            kept.icon = kept.icon.take().or(tag.icon);
+            for child in tag.children {
+                if !kept.children.contains(&child) {
+                    kept.children.push(child);
+                }
+            }
+            report.removed_tags.push(tag.name);
+        }
+        self.data.tags = tags;
+
+        if !dry_run && !report.is_empty() {
+            self.apply()?;
+        }
+        Ok(report)
+    }
+}
+
+/// what `dedupe` changed
+#[derive(Default)]
+pub struct DedupeReport {
+    /// the canonical path, and how many entries were merged into one
+    pub merged_files: Vec<(PathBuf, usize)>,
+    /// stored paths that were not canonical, like ones with a symlink in them
+    pub canonical_paths: usize,
+    /// files that had the name of another file, with the name they got
+    pub renamed_files: Vec<(PathBuf, String)>,
+    pub removed_connections: usize,
+    /// their description, colour, icon and children went to the tag that was kept
+    pub removed_tags: Vec<String>,
+}
+impl DedupeReport {
+    pub fn is_empty(&self) -> bool {
+        self.merged_files.is_empty()
+            && self.canonical_paths == 0
+            && self.renamed_files.is_empty()
+            && self.removed_connections == 0
+            && self.removed_tags.is_empty()
+    }
+}
+
+/// what `import` changed
+#[derive(Default)]
+pub struct ImportReport {
+    pub added_files: usize,
+    pub updated_files: usize,
+    pub removed_files: usize,
+    pub added_tags: usize,
+    pub updated_tags: usize,
+    pub removed_tags: usize,
+    /// values that are different in the database and the import, the merge strategy decided which one is kept
+    pub conflicts: Vec<ImportConflict>,
+    /// imported paths that do not exist on this machine, maybe a `--rewrite` is missing
+    pub missing_files: Vec<PathBuf>,
+    /// files whose name another file already had, and the name they got instead so they dont share its tags
+    pub renamed_files: Vec<(PathBuf, String)>,
+}
+
+pub struct ImportConflict {
+    /// the tag or file path
+    pub subject: String,
+    pub field: &'static str,
+    pub here: String,
+    pub incoming: String,
+}
+
+impl ImportReport {
+    fn count(&mut self, before: &Data, after: &Data) {
+        for file in &after.files {
+            match before.files.iter().find(|x| x.path == file.path) {
+                Some(old)
+                    if old == file
+                        && sorted_tags(before, &old.name) == sorted_tags(after, &file.name) => {}
+                Some(_) => self.updated_files += 1,
+                None => self.added_files += 1,
+            }
+        }
+        self.removed_files = before
+            .files
+            .iter()
+            .filter(|x| !after.files.iter().any(|y| y.path == x.path))
+            .count();
+
+        for tag in &after.tags {
+            match before.tags.iter().find(|x| x.name == tag.name) {
+                Some(old) if old == tag => {}
+                Some(_) => self.updated_tags += 1,
+                None => self.added_tags += 1,
+            }
+        }
+        self.removed_tags = before
+            .tags
+            .iter()
+            .filter(|x| !after.tags.iter().any(|y| y.name == x.name))
+            .count();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added_files + self.updated_files + self.removed_files == 0
+            && self.added_tags + self.updated_tags + self.removed_tags == 0
+    }
+}
+
+/// puts files back after an undo failed, the first error is already returned so these are only printed
+fn redo_moves(moves: &[FileMove]) {
+    for file_move in moves {
+        if let Err(e) = journal::redo_move(file_move) {
+            eprintln!("{}", e);
+        }
+    }
+}
+
+/// puts files back after a redo failed, the last one first
+fn undo_moves(moves: &[FileMove]) {
+    for file_move in moves.iter().rev() {
+        if let Err(e) = journal::undo_move(file_move) {
+            eprintln!("{}", e);
+        }
+    }
+}
+
+/// writes what changed to the `user.xdg.tags` attribute of the files, and warns about the ones where that fails
+fn mirror_xattr(changed: &[(&Path, Vec<&str>, Vec<&str>)]) {
+    let mut unsupported = 0;
+    let mut failed: Vec<(&Path, std::io::Error)> = vec![];
+    for (path, before, after) in changed {
+        let before: Vec<String> = before.iter().map(|x| x.to_string()).collect();
+        let after: Vec<String> = after.iter().map(|x| x.to_string()).collect();
+        match xdg_tags::update(path, &before, &after) {
+            Ok(()) => {}
+            // the file is gone, there is nothing to write the tags to
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) if e.kind() == std::io::ErrorKind::Unsupported => unsupported += 1,
+            Err(e) => failed.push((path, e)),
+        }
+    }
+    if unsupported > 0 {
+        eprintln!(
+            "{} files are on a filesystem without extended attributes, their tags are only in the database",
+            unsupported
+        );
+    }
+    if let Some((path, e)) = failed.first() {
+        eprintln!(
+            "could not write the tags of {} files to {}, e.g. {}, {}",
+            failed.len(),
+            xdg_tags::ATTRIBUTE,
+            path.display(),
+            e
+        );
+    }
+}
+
+fn sorted_tags<'a>(data: &'a Data, file_name: &str) -> Vec<&'a str> {
+    let mut tags: Vec<&str> = data
+        .connections
+        .iter()
+        .filter(|x| x.file_name == file_name)
+        .map(|x| x.tag_name.as_str())
+        .collect();
+    tags.sort();
+    tags
+}
+
+/// takes the incoming value where there is none yet, and records a conflict when both have a different one
+fn merge_value<T: PartialEq>(
+    here: &mut Option<T>,
+    incoming: Option<T>,
+    prefer_incoming: bool,
+    (subject, field): (&str, &'static str),
+    show: impl Fn(&T) -> String,
+    conflicts: &mut Vec<ImportConflict>,
+) {
+    let incoming = match incoming {
+        Some(incoming) => incoming,
+        None => return,
+    };
+    match here {
+        None => *here = Some(incoming),
+        Some(value) if *value == incoming => {}
+        Some(value) => {
+            conflicts.push(ImportConflict {
+                subject: subject.to_owned(),
+                field,
+                here: show(value),
+                incoming: show(&incoming),
+            });
+            if prefer_incoming {
+                *here = Some(incoming);
+            }
+        }
+    }
+}
+
+fn path_text(path: &Path) -> String {
+    escape_os_str(path.as_os_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tags::types::AddMode;
+
+    /// a database in its own directory under the temp dir, anything left from an earlier run is removed first
+    fn database(test: &str) -> (PathBuf, Database) {
+        let directory = std::env::temp_dir().join(format!("tf-db-{}-{}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        let location = directory.join("data.ron");
+        let database = Database::open(location.clone()).unwrap();
+        (location, database)
+    }
+
+    fn stored(path: &str) -> StoredFile {
+        let path = PathBuf::from(path);
+        StoredFile {
+            name: StoredFile::name_of(&path).unwrap(),
+            path,
+            last_used: 0,
+            use_count: 0,
+            mode: AddMode::None,
+            original: None,
+        }
+    }
+
+    fn tags_of(database: &Database, path: &str) -> Vec<String> {
+        let file = database
+            .list_files()
+            .into_iter()
+            .find(|x| x.path == Path::new(path))
+            .unwrap();
+        database.file_tag_names(&file.name)
+    }
+
+    #[test]
+    fn same_file_names_keep_their_own_tags() {
+        let (location, database) = database("same-names");
+        database
+            .add_tags(vec!["work".to_owned(), "docs".to_owned()])
+            .unwrap();
+        Database::open(location.clone())
+            .unwrap()
+            .add_files(
+                vec![stored("/src/a.txt"), stored("/src/b.txt")],
+                &["work".to_owned()],
+                &[],
+            )
+            .unwrap();
+        Database::open(location.clone())
+            .unwrap()
+            .add_files(vec![stored("/src/sub/a.txt")], &["docs".to_owned()], &[])
+            .unwrap();
+
+        let database = Database::open(location).unwrap();
+        let mut names: Vec<String> = database.list_files().into_iter().map(|x| x.name).collect();
+        names.sort();
+        assert_eq!(names, ["a (1).txt", "a.txt", "b.txt"]);
+        assert_eq!(tags_of(&database, "/src/a.txt"), ["work"]);
+        assert_eq!(tags_of(&database, "/src/b.txt"), ["work"]);
+        assert_eq!(tags_of(&database, "/src/sub/a.txt"), ["docs"]);
+    }
+
+    #[test]
+    fn same_file_names_in_one_call() {
+        let (location, database) = database("same-names-one-call");
+        database.add_tags(vec!["work".to_owned()]).unwrap();
+        Database::open(location.clone())
+            .unwrap()
+            .add_files(
+                vec![stored("/one/a.txt"), stored("/two/a.txt")],
+                &["work".to_owned()],
+                &[],
+            )
+            .unwrap();
+
+        let database = Database::open(location).unwrap();
+        let mut names: Vec<String> = database.list_files().into_iter().map(|x| x.name).collect();
+        names.sort();
+        assert_eq!(names, ["a (1).txt", "a.txt"]);
+        assert_eq!(tags_of(&database, "/one/a.txt"), ["work"]);
+        assert_eq!(tags_of(&database, "/two/a.txt"), ["work"]);
+    }
+
+    #[test]
+    fn deleting_a_file_removes_its_tags() {
+        let (location, database) = database("delete");
+        database.add_tags(vec!["work".to_owned()]).unwrap();
+        Database::open(location.clone())
+            .unwrap()
+            .add_files(
+                vec![stored("/one/a.txt"), stored("/two/a.txt")],
+                &["work".to_owned()],
+                &[],
+            )
+            .unwrap();
+        Database::open(location.clone())
+            .unwrap()
+            .delete_files(&[PathBuf::from("/one/a.txt")])
+            .unwrap();
+
+        let database = Database::open(location).unwrap();
+        assert_eq!(database.list_files().len(), 1);
+        assert_eq!(tags_of(&database, "/two/a.txt"), ["work"]);
+        assert!(database.file_tag_names("a.txt").is_empty());
+    }
+
+    #[test]
+    fn dedupe_gives_shared_names_their_own() {
+        let (location, _) = database("dedupe-names");
+        let mut data = Data {
+            files: vec![
+                stored("/one/a.txt"),
+                stored("/two/a.txt"),
+                stored("/one/a.txt"),
+            ],
+            connections: vec![],
+            tags: vec![Tag::new("work".to_owned())],
+            child_tags: vec![],
+        };
+        data.files[2].name = "old.txt".to_owned();
+        for file_name in ["a.txt", "old.txt"] {
+            data.connections.push(FileTagConnection {
+                file_name: file_name.to_owned(),
+                tag_name: "work".to_owned(),
+            });
+        }
+        std::fs::write(&location, Database::serialize(&data).unwrap()).unwrap();
+
+        let report = Database::open(location.clone())
+            .unwrap()
+            .dedupe(false)
+            .unwrap();
+        assert_eq!(report.merged_files.len(), 1);
+        assert_eq!(
+            report.renamed_files,
+            [(PathBuf::from("/two/a.txt"), "a (1).txt".to_owned())]
+        );
+        assert_eq!(report.removed_connections, 1);
+
+        let database = Database::open(location).unwrap();
+        assert_eq!(tags_of(&database, "/one/a.txt"), ["work"]);
+        assert_eq!(tags_of(&database, "/two/a.txt"), ["work"]);
+        assert!(database.file_tag_names("old.txt").is_empty());
     }
 }
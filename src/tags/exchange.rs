@@ -0,0 +1,255 @@
+use ron::ser::{to_string_pretty, PrettyConfig};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use super::tag_relations::validate_tag_name;
+use super::types::{
+    deserialize_optional_path, deserialize_path, serialize_optional_path, serialize_path, AddMode,
+    Tag,
+};
+use crate::error::Error;
+
+/// bumped when the export changes in a way older versions can not read
+pub const VERSION: u32 = 1;
+
+/// the whole database in a form that does not depend on how it is stored, for `export` and `import`
+#[derive(Deserialize, Serialize)]
+pub struct Export {
+    pub version: u32,
+    pub files: Vec<ExportedFile>,
+    /// csv has no room for these, tags that are only used by files are created without a description
+    #[serde(default)]
+    pub tags: Vec<Tag>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct ExportedFile {
+    pub name: String,
+    #[serde(
+        serialize_with = "serialize_path",
+        deserialize_with = "deserialize_path"
+    )]
+    pub path: PathBuf,
+    /// in the order the file got them
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub last_used: u64,
+    #[serde(default)]
+    pub use_count: u32,
+    #[serde(default)]
+    pub mode: AddMode,
+    #[serde(
+        default,
+        serialize_with = "serialize_optional_path",
+        deserialize_with = "deserialize_optional_path"
+    )]
+    pub original: Option<PathBuf>,
+}
+
+/// one file per row, csv cant hold lists so the tags are separated by spaces, which tag names cant contain
+#[derive(Deserialize, Serialize)]
+struct CsvRow {
+    name: String,
+    path: String,
+    tags: String,
+    #[serde(default)]
+    last_used: u64,
+    #[serde(default)]
+    use_count: u32,
+    #[serde(default)]
+    mode: AddMode,
+    #[serde(default)]
+    original: String,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Json,
+    Csv,
+    Ron,
+}
+
+/// what happens with the data that is already in the database
+#[derive(Clone, Copy, PartialEq)]
+pub enum Merge {
+    /// the database ends up with exactly what was imported
+    Replace,
+    /// both are kept, where they disagree the database wins
+    Union,
+    /// both are kept, where they disagree the import wins
+    PreferIncoming,
+}
+
+/// `old=new`, paths starting with `old` are moved to `new` when importing
+#[derive(Clone)]
+pub struct Rewrite {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+impl FromStr for Rewrite {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((from, to)) if !from.is_empty() && !to.is_empty() => Ok(Rewrite {
+                from: PathBuf::from(from),
+                to: PathBuf::from(to),
+            }),
+            _ => Err(format!(
+                "expected OLD=NEW like /home/old=/home/new, got '{}'",
+                s
+            )),
+        }
+    }
+}
+
+impl Export {
+    pub fn write(&self, encoding: Encoding) -> Result<String, Error> {
+        let serialize_error = |e: String| Error::Parse(format!("could not serialize, {}", e));
+        match encoding {
+            Encoding::Json => {
+                serde_json::to_string_pretty(self).map_err(|e| serialize_error(e.to_string()))
+            }
+            Encoding::Ron => to_string_pretty(self, PrettyConfig::new().indentor("  ".to_owned()))
+                .map_err(|e| serialize_error(e.to_string())),
+            Encoding::Csv => {
+                // json and ron keep the bytes of such paths, csv would have to change them
+                let text = |path: &Path| match path.to_str() {
+                    Some(text) => Ok(text.to_owned()),
+                    None => Err(Error::Invalid(format!(
+                        "{} is not valid utf-8, export as json or ron instead of csv",
+                        path.display()
+                    ))),
+                };
+                let mut writer = csv::Writer::from_writer(vec![]);
+                for file in &self.files {
+                    let row = CsvRow {
+                        name: file.name.clone(),
+                        path: text(&file.path)?,
+                        tags: file.tags.join(" "),
+                        last_used: file.last_used,
+                        use_count: file.use_count,
+                        mode: file.mode,
+                        original: match &file.original {
+                            Some(original) => text(original)?,
+                            None => String::new(),
+                        },
+                    };
+                    writer
+                        .serialize(row)
+                        .map_err(|e| serialize_error(e.to_string()))?;
+                }
+                let bytes = writer
+                    .into_inner()
+                    .map_err(|e| serialize_error(e.to_string()))?;
+                Ok(String::from_utf8_lossy(&bytes).into_owned())
+            }
+        }
+    }
+
+    /// without an encoding it is guessed from the first character, `{` for json, `(` for ron and csv otherwise
+    pub fn read(text: &str, encoding: Option<Encoding>) -> Result<Export, Error> {
+        let encoding = encoding.unwrap_or(match text.trim_start().chars().next() {
+            Some('{') => Encoding::Json,
+            Some('(') => Encoding::Ron,
+            _ => Encoding::Csv,
+        });
+        let parse_error = |e: String| Error::Parse(format!("could not read the import, {}", e));
+
+        match encoding {
+            Encoding::Json => serde_json::from_str(text).map_err(|e| parse_error(e.to_string())),
+            Encoding::Ron => ron::from_str(text).map_err(|e| parse_error(e.to_string())),
+            Encoding::Csv => {
+                let mut files = vec![];
+                for row in csv::Reader::from_reader(text.as_bytes()).deserialize() {
+                    let row: CsvRow = row.map_err(|e| parse_error(e.to_string()))?;
+                    files.push(ExportedFile {
+                        name: row.name,
+                        path: PathBuf::from(row.path),
+                        tags: row.tags.split_whitespace().map(|x| x.to_owned()).collect(),
+                        last_used: row.last_used,
+                        use_count: row.use_count,
+                        mode: row.mode,
+                        original: Some(PathBuf::from(row.original))
+                            .filter(|x| !x.as_os_str().is_empty()),
+                    });
+                }
+                Ok(Export {
+                    version: VERSION,
+                    files,
+                    tags: vec![],
+                })
+            }
+        }
+    }
+
+    /// moves the paths and originals under the first rewrite that matches them
+    pub fn rewrite(&mut self, rewrites: &[Rewrite]) {
+        let rewrite = |path: &PathBuf| -> PathBuf {
+            for rewrite in rewrites {
+                // goes by components, so /home/old does not match /home/older
+                if let Ok(rest) = path.strip_prefix(&rewrite.from) {
+                    return rewrite.to.join(rest);
+                }
+            }
+            path.clone()
+        };
+
+        for file in &mut self.files {
+            file.path = rewrite(&file.path);
+            file.original = file.original.as_ref().map(rewrite);
+        }
+    }
+
+    /// everything that is wrong with the import, so it can be fixed in one go instead of one error at a time
+    pub fn validate(&self) -> Result<(), Error> {
+        let mut problems: Vec<String> = vec![];
+
+        if self.version > VERSION {
+            problems.push(format!(
+                "it is version {}, this version of TaggedFiles can only read up to version {}",
+                self.version, VERSION
+            ));
+        }
+
+        for (i, tag) in self.tags.iter().enumerate() {
+            if let Err(e) = validate_tag_name(&tag.name) {
+                problems.push(e.to_string());
+            }
+            if self.tags[..i].iter().any(|x| x.name == tag.name) {
+                problems.push(format!("the tag '{}' is in it more than once", tag.name));
+            }
+        }
+
+        for (i, file) in self.files.iter().enumerate() {
+            if file.name.is_empty() {
+                problems.push(format!("{} has no name", file.path.display()));
+            }
+            if !file.path.is_absolute() {
+                problems.push(format!("the path {} is not absolute", file.path.display()));
+            }
+            if self.files[..i].iter().any(|x| x.path == file.path) {
+                problems.push(format!(
+                    "the path {} is in it more than once",
+                    file.path.display()
+                ));
+            }
+            for tag in &file.tags {
+                if let Err(e) = validate_tag_name(tag) {
+                    problems.push(format!("{}: {}", file.path.display(), e));
+                }
+            }
+        }
+
+        if problems.is_empty() {
+            return Ok(());
+        }
+        Err(Error::Invalid(format!(
+            "the import was not applied, it has {} problems:\n  {}",
+            problems.len(),
+            problems.join("\n  ")
+        )))
+    }
+}
@@ -0,0 +1,437 @@
+use ron::ser::{to_string_pretty, PrettyConfig};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::transfer;
+use super::types::{deserialize_path, serialize_path, AddMode};
+use crate::error::Error;
+
+/// only the last changes are kept, so the journal does not grow forever
+const MAX_ENTRIES: usize = 200;
+
+/// every change to the database, `undo` and `redo` walk through it
+#[derive(Deserialize, Serialize, Default)]
+pub struct Journal {
+    pub entries: Vec<Entry>,
+    /// the entries before this index are done, the ones after it are undone and can be redone
+    pub position: usize,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct Entry {
+    /// seconds since the unix epoch
+    pub time: u64,
+    /// the command that made the change, as it could be pasted in a shell
+    pub command: String,
+    pub changes: Vec<Change>,
+    /// files that were moved on disk, in the order they were moved
+    #[serde(default)]
+    pub moves: Vec<FileMove>,
+}
+
+/// one item that was added to or removed from a list in the database
+///
+/// a changed item is removed and added again, the index is where it was or is in the list
+#[derive(Deserialize, Serialize, Clone)]
+pub enum Change {
+    Added(usize, Item),
+    Removed(usize, Item),
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+pub enum Item {
+    File(super::types::StoredFile),
+    Connection(super::types::FileTagConnection),
+    Tag(super::types::Tag),
+    ChildTag(super::types::ChildTag),
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct FileMove {
+    #[serde(
+        serialize_with = "serialize_path",
+        deserialize_with = "deserialize_path"
+    )]
+    pub from: PathBuf,
+    #[serde(
+        serialize_with = "serialize_path",
+        deserialize_with = "deserialize_path"
+    )]
+    pub to: PathBuf,
+    /// older journals only had moves
+    #[serde(default = "default_mode")]
+    pub mode: AddMode,
+}
+
+fn default_mode() -> AddMode {
+    AddMode::Move
+}
+
+impl Entry {
+    /// a short description like `+2 files, -1 tag`
+    pub fn summary(&self) -> String {
+        let mut counts: Vec<(&str, i64, i64)> = vec![
+            ("file", 0, 0),
+            ("connection", 0, 0),
+            ("tag", 0, 0),
+            ("child tag", 0, 0),
+        ];
+        for change in &self.changes {
+            let (added, item) = match change {
+                Change::Added(_, item) => (true, item),
+                Change::Removed(_, item) => (false, item),
+            };
+            let index = match item {
+                Item::File(_) => 0,
+                Item::Connection(_) => 1,
+                Item::Tag(_) => 2,
+                Item::ChildTag(_) => 3,
+            };
+            if added {
+                counts[index].1 += 1;
+            } else {
+                counts[index].2 += 1;
+            }
+        }
+
+        let mut parts: Vec<String> = vec![];
+        for (name, added, removed) in counts {
+            // an item that was removed and added again was changed
+            let changed = added.min(removed);
+            for (sign, count) in [
+                ("+", added - changed),
+                ("-", removed - changed),
+                ("~", changed),
+            ] {
+                match count {
+                    0 => {}
+                    1 => parts.push(format!("{}1 {}", sign, name)),
+                    _ => parts.push(format!("{}{} {}s", sign, count, name)),
+                }
+            }
+        }
+        for mode in [
+            AddMode::Move,
+            AddMode::Copy,
+            AddMode::Link,
+            AddMode::Hardlink,
+        ] {
+            let count = self.moves.iter().filter(|x| x.mode == mode).count();
+            if count > 0 {
+                parts.push(format!("{} {}", count, mode.past_tense()));
+            }
+        }
+        parts.join(", ")
+    }
+}
+
+/// the changes that turn `before` into `after`
+///
+/// items keep their order in this database, so everything that is not in both lists was added or removed
+pub fn diff<T: Clone + PartialEq>(before: &[T], after: &[T], wrap: fn(T) -> Item) -> Vec<Change> {
+    let mut changes = vec![];
+
+    let mut remaining: Vec<&T> = after.iter().collect();
+    for (i, item) in before.iter().enumerate() {
+        match remaining.iter().position(|x| *x == item) {
+            Some(position) => _ = remaining.remove(position),
+            None => changes.push(Change::Removed(i, wrap(item.clone()))),
+        }
+    }
+
+    let mut remaining: Vec<&T> = before.iter().collect();
+    for (i, item) in after.iter().enumerate() {
+        match remaining.iter().position(|x| *x == item) {
+            Some(position) => _ = remaining.remove(position),
+            None => changes.push(Change::Added(i, wrap(item.clone()))),
+        }
+    }
+
+    changes
+}
+
+/// the changes that undo these changes
+pub fn invert(changes: &[Change]) -> Vec<Change> {
+    changes
+        .iter()
+        .map(|x| match x {
+            Change::Added(i, item) => Change::Removed(*i, item.clone()),
+            Change::Removed(i, item) => Change::Added(*i, item.clone()),
+        })
+        .collect()
+}
+
+/// applies the changes to one list
+///
+/// removals go by `same`, so a file is still found after its last used time changed, additions go in at their old index
+pub fn apply<T: Clone>(
+    list: &mut Vec<T>,
+    changes: &[Change],
+    unwrap: fn(&Item) -> Option<&T>,
+    same: fn(&T, &T) -> bool,
+) {
+    let mut removed: Vec<&T> = changes
+        .iter()
+        .filter_map(|x| match x {
+            Change::Removed(_, item) => unwrap(item),
+            _ => None,
+        })
+        .collect();
+    list.retain(|x| match removed.iter().position(|y| same(x, y)) {
+        Some(position) => {
+            removed.remove(position);
+            false
+        }
+        None => true,
+    });
+
+    let mut added: Vec<(usize, &T)> = changes
+        .iter()
+        .filter_map(|x| match x {
+            Change::Added(i, item) => unwrap(item).map(|item| (*i, item)),
+            _ => None,
+        })
+        .collect();
+    added.sort_by_key(|x| x.0);
+    // items that were in the list more than once are added back more than once, so no `same` check here
+    for (i, item) in added {
+        list.insert(i.min(list.len()), item.clone());
+    }
+}
+
+/// does what `addfile` did to the file again, refuses to overwrite anything
+pub fn redo_move(file_move: &FileMove) -> Result<(), Error> {
+    if fs::symlink_metadata(&file_move.to).is_ok() {
+        return Err(Error::Invalid(format!(
+            "cant put {} at {}, it already exists",
+            file_move.from.display(),
+            file_move.to.display()
+        )));
+    }
+    if let Some(parent) = file_move.to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if let Err(e) = transfer::transfer(&file_move.from, &file_move.to, file_move.mode) {
+        return Err(Error::Io(format!(
+            "could not put {} at {}, {}",
+            file_move.from.display(),
+            file_move.to.display(),
+            e
+        )));
+    }
+    Ok(())
+}
+
+/// puts the file back where it was before `addfile`, refuses to overwrite anything
+pub fn undo_move(file_move: &FileMove) -> Result<(), Error> {
+    // a link mode leaves a symlink at the original path, that one is replaced
+    let occupied = match fs::symlink_metadata(&file_move.from) {
+        Ok(metadata) => !(file_move.mode == AddMode::Link && metadata.file_type().is_symlink()),
+        Err(_) => false,
+    };
+    if occupied && matches!(file_move.mode, AddMode::Move | AddMode::Link) {
+        return Err(Error::Invalid(format!(
+            "cant move {} to {}, it already exists",
+            file_move.to.display(),
+            file_move.from.display()
+        )));
+    }
+    // `reorganize` removes directories that it emptied
+    if let Some(parent) = file_move.from.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if let Err(e) = transfer::revert(&file_move.from, &file_move.to, file_move.mode) {
+        return Err(Error::Io(format!(
+            "could not put {} back at {}, {}",
+            file_move.to.display(),
+            file_move.from.display(),
+            e
+        )));
+    }
+    Ok(())
+}
+
+impl Journal {
+    pub fn read(path: &Path) -> Result<Journal, Error> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Journal::default()),
+            Err(e) => return Err(e.into()),
+        };
+        match ron::from_str(&text) {
+            Ok(journal) => Ok(journal),
+            Err(e) => Err(Error::Parse(format!(
+                "could not read {}, {}",
+                path.display(),
+                e
+            ))),
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        let text = match to_string_pretty(self, PrettyConfig::new().indentor("  ".to_owned())) {
+            Ok(text) => text,
+            Err(e) => {
+                return Err(Error::Parse(format!(
+                    "could not serialize the journal, {}",
+                    e
+                )))
+            }
+        };
+        fs::write(path, text)?;
+        Ok(())
+    }
+
+    /// adds an entry, the undone entries after the current position can no longer be redone
+    pub fn push(&mut self, entry: Entry) {
+        self.entries.truncate(self.position);
+        self.entries.push(entry);
+        if self.entries.len() > MAX_ENTRIES {
+            let excess = self.entries.len() - MAX_ENTRIES;
+            self.entries.drain(..excess);
+        }
+        self.position = self.entries.len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tags::types::{FileTagConnection, Tag};
+
+    fn connection(file_name: &str, tag_name: &str) -> FileTagConnection {
+        FileTagConnection {
+            file_name: file_name.to_owned(),
+            tag_name: tag_name.to_owned(),
+        }
+    }
+
+    fn unwrap_connection(item: &Item) -> Option<&FileTagConnection> {
+        match item {
+            Item::Connection(connection) => Some(connection),
+            _ => None,
+        }
+    }
+
+    fn unwrap_tag(item: &Item) -> Option<&Tag> {
+        match item {
+            Item::Tag(tag) => Some(tag),
+            _ => None,
+        }
+    }
+
+    /// applying the diff to `before` gives `after`, and applying it inverted to `after` gives `before`
+    fn round_trip<T: Clone + PartialEq + std::fmt::Debug>(
+        before: Vec<T>,
+        after: Vec<T>,
+        wrap: fn(T) -> Item,
+        unwrap: fn(&Item) -> Option<&T>,
+        same: fn(&T, &T) -> bool,
+    ) {
+        let changes = diff(&before, &after, wrap);
+
+        let mut done = before.clone();
+        apply(&mut done, &changes, unwrap, same);
+        assert_eq!(done, after);
+
+        let mut undone = after.clone();
+        apply(&mut undone, &invert(&changes), unwrap, same);
+        assert_eq!(undone, before);
+    }
+
+    #[test]
+    fn nothing_changed() {
+        let list = vec![connection("a", "x"), connection("b", "y")];
+        assert!(diff(&list, &list, Item::Connection).is_empty());
+        round_trip(
+            list.clone(),
+            list,
+            Item::Connection,
+            unwrap_connection,
+            |a, b| a == b,
+        );
+    }
+
+    #[test]
+    fn added_and_removed() {
+        round_trip(
+            vec![
+                connection("a", "x"),
+                connection("b", "y"),
+                connection("c", "z"),
+            ],
+            vec![
+                connection("a", "x"),
+                connection("c", "z"),
+                connection("d", "x"),
+            ],
+            Item::Connection,
+            unwrap_connection,
+            |a, b| a == b,
+        );
+    }
+
+    #[test]
+    fn added_in_the_middle() {
+        round_trip(
+            vec![connection("a", "x"), connection("c", "z")],
+            vec![
+                connection("a", "x"),
+                connection("b", "y"),
+                connection("c", "z"),
+            ],
+            Item::Connection,
+            unwrap_connection,
+            |a, b| a == b,
+        );
+    }
+
+    #[test]
+    fn from_and_to_empty() {
+        let list = vec![connection("a", "x"), connection("b", "y")];
+        round_trip(
+            vec![],
+            list.clone(),
+            Item::Connection,
+            unwrap_connection,
+            |a, b| a == b,
+        );
+        round_trip(list, vec![], Item::Connection, unwrap_connection, |a, b| {
+            a == b
+        });
+    }
+
+    #[test]
+    fn changed_item_keeps_its_place() {
+        let mut described = Tag::new("b".to_owned());
+        described.description = Some("bee".to_owned());
+        let before = vec![
+            Tag::new("a".to_owned()),
+            Tag::new("b".to_owned()),
+            Tag::new("c".to_owned()),
+        ];
+        let after = vec![
+            Tag::new("a".to_owned()),
+            described,
+            Tag::new("c".to_owned()),
+        ];
+
+        let changes = diff(&before, &after, Item::Tag);
+        assert_eq!(changes.len(), 2);
+        round_trip(before, after, Item::Tag, unwrap_tag, |a, b| {
+            a.name == b.name
+        });
+    }
+
+    #[test]
+    fn repeated_items() {
+        round_trip(
+            vec![connection("a", "x"), connection("a", "x")],
+            vec![connection("a", "x")],
+            Item::Connection,
+            unwrap_connection,
+            |a, b| a == b,
+        );
+    }
+}
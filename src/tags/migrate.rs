@@ -0,0 +1,295 @@
+use rusqlite::{Connection, OpenFlags};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::exchange::{self, Export, ExportedFile};
+use super::transfer;
+use super::types::{StoredFile, Tag};
+use crate::error::Error;
+
+/// what was read from another program, and what had to change to fit in here
+#[derive(Default)]
+pub struct Mapping {
+    pub files: usize,
+    pub tags: usize,
+    /// tmsu tags with a value like `year=2017`, they become the tags `year` and `year:2017`
+    pub value_tags: usize,
+    /// tag names that are not valid here, and what they became
+    pub renamed: Vec<(String, String)>,
+    /// what could not be read or has nothing like it here
+    pub notes: Vec<String>,
+}
+
+impl Mapping {
+    /// a tag name that is valid here, the characters that are not allowed become `_`
+    fn tag_name(&mut self, name: &str) -> String {
+        let mapped: String = name
+            .trim()
+            .chars()
+            .map(|c| match c {
+                '/' | '+' | '-' | '=' => '_',
+                c if c.is_whitespace() => '_',
+                c => c,
+            })
+            .collect();
+        if mapped != name && !self.renamed.iter().any(|x| x.0 == name) {
+            self.renamed.push((name.to_owned(), mapped.clone()));
+        }
+        mapped
+    }
+
+    /// the export with every tag that is used, and the counts filled in
+    ///
+    /// tags go by file name here, files with a name another file already has get one like `README (1).md`
+    fn finish(&mut self, mut files: Vec<ExportedFile>, mut tags: Vec<String>) -> Export {
+        let mut renamed = 0;
+        for i in 0..files.len() {
+            let name = files[i].name.clone();
+            let mut unique = name.clone();
+            let mut n = 1;
+            while files[..i].iter().any(|x| x.name == unique) {
+                unique = transfer::numbered_name(Path::new(&name), n);
+                n += 1;
+            }
+            if unique != name {
+                renamed += 1;
+                files[i].name = unique;
+            }
+        }
+        if renamed > 0 {
+            self.notes.push(format!(
+                "{} files have the same name as another file, they got names like `name (1).ext` so they keep their own tags",
+                renamed
+            ));
+        }
+
+        for file in &files {
+            for tag in &file.tags {
+                if !tags.contains(tag) {
+                    tags.push(tag.clone());
+                }
+            }
+        }
+        self.files = files.len();
+        self.tags = tags.len();
+
+        Export {
+            version: exchange::VERSION,
+            files,
+            tags: tags.into_iter().map(Tag::new).collect(),
+        }
+    }
+}
+
+/// adds the tag to the file if it does not have it yet, and the file if it is not there yet
+fn tag_file(files: &mut Vec<ExportedFile>, path: PathBuf, tag: Option<String>) {
+    let index = match files.iter().position(|x| x.path == path) {
+        Some(index) => index,
+        None => {
+            files.push(ExportedFile {
+                name: StoredFile::name_of(&path).unwrap_or_default(),
+                path,
+                tags: vec![],
+                last_used: 0,
+                use_count: 0,
+                mode: Default::default(),
+                original: None,
+            });
+            files.len() - 1
+        }
+    };
+    if let Some(tag) = tag {
+        if !files[index].tags.contains(&tag) {
+            files[index].tags.push(tag);
+        }
+    }
+}
+
+/// reads the sqlite database of tmsu, usually `.tmsu/db`
+pub fn tmsu(database: &Path) -> Result<(Export, Mapping), Error> {
+    if !database.is_file() {
+        return Err(Error::NotFound(format!(
+            "there is no tmsu database at {}",
+            database.display()
+        )));
+    }
+    // relative paths in the database are relative to where it is
+    let database = &fs::canonicalize(database)?;
+    let read_error = |e: rusqlite::Error| {
+        Error::Parse(format!(
+            "could not read the tmsu database {}, {}",
+            database.display(),
+            e
+        ))
+    };
+    let connection = Connection::open_with_flags(database, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(read_error)?;
+
+    // newer versions store paths relative to the directory that has the `.tmsu` directory
+    let parent = database.parent().unwrap_or(Path::new(""));
+    let root = match parent.file_name() {
+        Some(name) if name == ".tmsu" => parent.parent().unwrap_or(parent),
+        _ => parent,
+    };
+
+    let mut mapping = Mapping::default();
+
+    let mut statement = connection
+        .prepare("SELECT name FROM tag ORDER BY id")
+        .map_err(read_error)?;
+    let tag_names = statement
+        .query_map([], |row| row.get::<_, String>(0))
+        .and_then(|rows| rows.collect::<Result<Vec<String>, rusqlite::Error>>())
+        .map_err(read_error)?;
+    let tags: Vec<String> = tag_names.iter().map(|x| mapping.tag_name(x)).collect();
+
+    let mut statement = connection
+        .prepare(
+            "SELECT file.directory, file.name, tag.name, value.name FROM file
+            LEFT JOIN file_tag ON file_tag.file_id = file.id
+            LEFT JOIN tag ON tag.id = file_tag.tag_id
+            LEFT JOIN value ON value.id = file_tag.value_id
+            ORDER BY file.id",
+        )
+        .map_err(read_error)?;
+    type Row = (String, String, Option<String>, Option<String>);
+    let rows = statement
+        .query_map([], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+        })
+        .and_then(|rows| rows.collect::<Result<Vec<Row>, rusqlite::Error>>())
+        .map_err(read_error)?;
+
+    let mut files: Vec<ExportedFile> = vec![];
+    for (directory, name, tag, value) in rows {
+        // the directory of files in the root is `.`, collecting the components drops it
+        let path: PathBuf = root.join(directory).join(name).components().collect();
+        let tag = tag.map(|x| mapping.tag_name(&x));
+        match (tag, value.filter(|x| !x.is_empty())) {
+            (Some(tag), Some(value)) => {
+                mapping.value_tags += 1;
+                let value = mapping.tag_name(&value);
+                tag_file(&mut files, path.clone(), Some(tag.clone()));
+                tag_file(&mut files, path, Some(format!("{}:{}", tag, value)));
+            }
+            (tag, _) => tag_file(&mut files, path, tag),
+        }
+    }
+
+    // older databases do not have implications
+    let implications: usize = connection
+        .query_row("SELECT count(*) FROM implication", [], |row| row.get(0))
+        .unwrap_or(0);
+    if implications > 0 {
+        mapping.notes.push(format!(
+            "{} tag implications were not imported, there is nothing like them here",
+            implications
+        ));
+    }
+
+    let export = mapping.finish(files, tags);
+    Ok((export, mapping))
+}
+
+/// reads the tags tagspaces puts in file names like `beach[summer 2020].jpg`, and in the sidecar files in `.ts` directories
+pub fn tagspaces(directory: &Path) -> Result<(Export, Mapping), Error> {
+    let directory = match fs::canonicalize(directory) {
+        Ok(directory) if directory.is_dir() => directory,
+        _ => {
+            return Err(Error::NotFound(format!(
+                "there is no directory at {}",
+                directory.display()
+            )))
+        }
+    };
+
+    let mut mapping = Mapping::default();
+    let mut files: Vec<ExportedFile> = vec![];
+    walk_tagspaces(&directory, &mut mapping, &mut files);
+    let export = mapping.finish(files, vec![]);
+    Ok((export, mapping))
+}
+
+fn walk_tagspaces(directory: &Path, mapping: &mut Mapping, files: &mut Vec<ExportedFile>) {
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(e) => {
+            mapping
+                .notes
+                .push(format!("could not read {}, {}", directory.display(), e));
+            return;
+        }
+    };
+    let mut paths: Vec<PathBuf> = entries.flatten().map(|x| x.path()).collect();
+    paths.sort();
+
+    for path in paths {
+        let name = match path.file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => continue,
+        };
+        if name == ".ts" {
+            continue;
+        }
+
+        let mut tags: Vec<String> = vec![];
+        let sidecar = if path.is_dir() {
+            walk_tagspaces(&path, mapping, files);
+            // the tags of a directory are in its own `.ts` directory
+            path.join(".ts").join("tsm.json")
+        } else {
+            tags.extend(name_tags(&name));
+            directory.join(".ts").join(format!("{}.json", name))
+        };
+        tags.extend(sidecar_tags(&sidecar, mapping));
+
+        for tag in tags.iter().filter(|x| !x.trim().is_empty()) {
+            let tag = mapping.tag_name(tag);
+            tag_file(files, path.clone(), Some(tag));
+        }
+    }
+}
+
+/// the tags between the last brackets before the extension, `beach[summer 2020].jpg` has `summer` and `2020`
+fn name_tags(name: &str) -> Vec<String> {
+    let stem = match name.rfind('.') {
+        Some(i) if i > 0 => &name[..i],
+        _ => name,
+    };
+    let start = match (stem.ends_with(']'), stem.rfind('[')) {
+        (true, Some(start)) => start,
+        _ => return vec![],
+    };
+    stem[start + 1..stem.len() - 1]
+        .split_whitespace()
+        .map(|x| x.to_owned())
+        .collect()
+}
+
+/// the titles of the tags in a sidecar like `{"tags": [{"title": "summer"}]}`, nothing if there is no sidecar
+fn sidecar_tags(path: &Path, mapping: &mut Mapping) -> Vec<String> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(_) => return vec![],
+    };
+    // tagspaces writes a byte order mark in front of some of them
+    let json: serde_json::Value = match serde_json::from_str(text.trim_start_matches('\u{feff}')) {
+        Ok(json) => json,
+        Err(e) => {
+            mapping
+                .notes
+                .push(format!("could not read {}, {}", path.display(), e));
+            return vec![];
+        }
+    };
+
+    json["tags"]
+        .as_array()
+        .map(|tags| {
+            tags.iter()
+                .filter_map(|x| x["title"].as_str())
+                .map(|x| x.to_owned())
+                .collect()
+        })
+        .unwrap_or_default()
+}
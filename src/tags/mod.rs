@@ -1,3 +1,9 @@
 pub mod db;
+pub mod exchange;
+pub mod journal;
+pub mod migrate;
+pub mod sidecar;
 pub mod tag_relations;
+pub mod transfer;
 pub mod types;
+pub mod xdg_tags;
@@ -0,0 +1,87 @@
+use ron::ser::{to_string_pretty, PrettyConfig};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::error::Error;
+
+/// the sidecar in every directory with tagged files, so the tags move with a copied directory
+pub const FILE_NAME: &str = ".tagged.ron";
+/// where it is written before it is renamed over the sidecar
+const TEMP_NAME: &str = ".tagged.ron.tmp";
+
+#[derive(Deserialize, Serialize, Default, PartialEq)]
+pub struct Sidecar {
+    /// the file names in the directory and their tags, in the order the files got them
+    pub files: BTreeMap<String, Vec<String>>,
+}
+
+/// the sidecar of a directory, `None` if it has none
+pub fn read(directory: &Path) -> Result<Option<Sidecar>, Error> {
+    let path = directory.join(FILE_NAME);
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(Error::Io(format!(
+                "could not read {}, {}",
+                path.display(),
+                e
+            )))
+        }
+    };
+    match ron::from_str(&text) {
+        Ok(sidecar) => Ok(Some(sidecar)),
+        Err(e) => Err(Error::Parse(format!(
+            "could not read {}, {}",
+            path.display(),
+            e
+        ))),
+    }
+}
+
+/// replaces the sidecar of a directory, and removes it when no file there has tags
+pub fn write(directory: &Path, sidecar: &Sidecar) -> io::Result<()> {
+    let path = directory.join(FILE_NAME);
+    if sidecar.files.is_empty() {
+        return match fs::remove_file(&path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        };
+    }
+
+    let text = to_string_pretty(sidecar, PrettyConfig::new().indentor("  ".to_owned()))
+        .map_err(io::Error::other)?;
+    // written next to it and renamed, so a full disk never leaves half a sidecar
+    let temp_path = directory.join(TEMP_NAME);
+    fs::write(&temp_path, text)?;
+    fs::rename(&temp_path, &path)
+}
+
+/// whether the path is a sidecar or one that is being written, those are never tracked themselves
+pub fn is_sidecar(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|x| x == FILE_NAME || x == TEMP_NAME)
+}
+
+/// the directories under the root that have a sidecar, symlinks are not followed so the link directory is skipped
+pub fn find(root: &Path, found: &mut Vec<PathBuf>) {
+    if root.join(FILE_NAME).is_file() {
+        found.push(root.to_path_buf());
+    }
+    let entries = match fs::read_dir(root) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    let mut directories: Vec<PathBuf> = entries
+        .flatten()
+        .filter(|x| x.file_type().is_ok_and(|x| x.is_dir()))
+        .map(|x| x.path())
+        .collect();
+    directories.sort();
+    for directory in directories {
+        find(&directory, found);
+    }
+}
@@ -1,9 +1,24 @@
 use std::cmp;
 
-use super::types::{ChildTag, Tag, TagFilter};
+use super::types::TagFilter;
+use crate::error::Error;
+
+/// tag names are used in the query syntax, so they cant contain the characters that have a meaning there
+pub fn validate_tag_name(name: &str) -> Result<(), Error> {
+    if name.is_empty() {
+        return Err(Error::Invalid("tag names cannot be empty".to_owned()));
+    }
+    if name.contains(['/', '+', '-', '=']) || name.contains(char::is_whitespace) {
+        return Err(Error::Invalid(format!(
+            "invalid tag name '{}', tag names cannot contain '/', '+', '-', '=' or whitespace",
+            name
+        )));
+    }
+    Ok(())
+}
 
 // for now we do not allow child of child tags
-pub fn parse_tags(tags: &Vec<String>) -> Result<TagFilter, String> {
+pub fn parse_tags(tags: &[String]) -> Result<TagFilter, Error> {
     let mut allowed_tags: Vec<String> = vec![];
     let mut denied_tags: Vec<String> = vec![];
     let mut allowed_child_tags: Vec<(String, String)> = vec![];
@@ -23,12 +38,12 @@ pub fn parse_tags(tags: &Vec<String>) -> Result<TagFilter, String> {
         )?;
     }
 
-    return Ok(TagFilter {
+    Ok(TagFilter {
         allowed_tags,
         denied_tags,
         allowed_child_tags,
         denied_child_tags,
-    });
+    })
 }
 
 fn do_tag_parsing(
@@ -36,8 +51,8 @@ fn do_tag_parsing(
     denied_tags: &mut Vec<String>,
     allowed_child_tags: &mut Vec<(String, String)>,
     denied_child_tags: &mut Vec<(String, String)>,
-    tag_string: &String,
-) -> Result<(), String> {
+    tag_string: &str,
+) -> Result<(), Error> {
     // walk till slash and do children, if end of line its done
 
     let slash_location = tag_string.find("/");
@@ -58,19 +73,19 @@ fn do_tag_parsing(
     }
 
     // TODO extract negative positive thing to function
-    if parent_tag_string.starts_with('=') {
+    if let Some(denied) = parent_tag_string.strip_prefix('=') {
         if slash_location.is_some() {
             // TODO think of a maybe better solution than just an error
-            return Err("having a denied parent tag with child tags to check for is kind of useless/contradictory".to_owned());
+            return Err(Error::Invalid("having a denied parent tag with child tags to check for is kind of useless/contradictory".to_owned()));
         }
-        denied_tags.push(parent_tag_string[1..].to_owned())
-    } else if parent_tag_string.starts_with('+') {
-        allowed_tags.push(parent_tag_string[1..].to_owned())
+        denied_tags.push(denied.to_owned())
+    } else if let Some(allowed) = parent_tag_string.strip_prefix('+') {
+        allowed_tags.push(allowed.to_owned())
     } else {
         allowed_tags.push(parent_tag_string.to_owned())
     }
 
-    return Ok(());
+    Ok(())
 }
 
 fn do_child_tags_recursive(
@@ -78,22 +93,22 @@ fn do_child_tags_recursive(
     denied_child_tags: &mut Vec<(String, String)>,
     children_tag_string: String,
     parent_tag_string: String,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     if children_tag_string.is_empty() {
-        return Err("Expected another child tag".to_owned());
+        return Err(Error::Invalid("Expected another child tag".to_owned()));
     }
 
     let positive;
     let new_children_string;
 
-    if children_tag_string.starts_with('=') {
+    if let Some(rest) = children_tag_string.strip_prefix('=') {
         positive = false;
         // cut of first char
-        new_children_string = children_tag_string[1..].to_owned();
-    } else if children_tag_string.starts_with('+') {
+        new_children_string = rest.to_owned();
+    } else if let Some(rest) = children_tag_string.strip_prefix('+') {
         positive = true;
         // cut of first char
-        new_children_string = children_tag_string[1..].to_owned();
+        new_children_string = rest.to_owned();
     } else {
         positive = true;
         new_children_string = children_tag_string;
@@ -137,5 +152,5 @@ fn do_child_tags_recursive(
         }
     }
 
-    return Ok(());
+    Ok(())
 }
@@ -0,0 +1,307 @@
+use std::fs::{self, File, FileTimes};
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use super::types::AddMode;
+
+/// moves a file or directory, with a rename when it stays on the same filesystem
+///
+/// otherwise it is copied with its permissions and timestamps, the copy is compared to the source and only then the source is removed
+pub fn move_path(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Ok(()) => return Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {}
+        Err(e) => return Err(e),
+    }
+    move_across(from, to)
+}
+
+/// the part of `move_path` for another filesystem
+fn move_across(from: &Path, to: &Path) -> io::Result<()> {
+    // if this fails the source is still complete
+    copy_verified(from, to)?;
+    remove_path(from)
+}
+
+/// puts the file at `to` the way the mode says, `None` does nothing
+pub fn transfer(from: &Path, to: &Path, mode: AddMode) -> io::Result<()> {
+    match mode {
+        AddMode::None => Ok(()),
+        AddMode::Move => move_path(from, to),
+        AddMode::Copy => copy_verified(from, to),
+        AddMode::Link => move_and_link(from, to, |from, to| symlink::symlink_auto(to, from)),
+        AddMode::Hardlink => fs::hard_link(from, to),
+    }
+}
+
+/// moves the file and leaves a link with `link` at the old path
+fn move_and_link(
+    from: &Path,
+    to: &Path,
+    link: impl FnOnce(&Path, &Path) -> io::Result<()>,
+) -> io::Result<()> {
+    move_path(from, to)?;
+    if let Err(e) = link(from, to) {
+        // put it back, a moved file without the link would break the old path
+        let _ = move_path(to, from);
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// undoes `transfer`, so the file is only at `from` again
+pub fn revert(from: &Path, to: &Path, mode: AddMode) -> io::Result<()> {
+    match mode {
+        AddMode::None => Ok(()),
+        AddMode::Move => move_path(to, from),
+        AddMode::Copy | AddMode::Hardlink => remove_path(to),
+        AddMode::Link => {
+            if fs::symlink_metadata(from)?.file_type().is_symlink() {
+                fs::remove_file(from)?;
+            }
+            move_path(to, from)
+        }
+    }
+}
+
+/// copies with permissions and timestamps, and checks the copy
+pub fn copy_verified(from: &Path, to: &Path) -> io::Result<()> {
+    if let Err(e) = copy_path(from, to).and_then(|_| verify(from, to)) {
+        let _ = remove_path(to);
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// a free path in the directory for this name, `name (1).ext`, `name (2).ext` and so on
+pub fn free_name(directory: &Path, name: &Path) -> PathBuf {
+    free_name_with(directory, name, |_| false)
+}
+
+/// like `free_name`, but also skips the paths `taken` says are in use, for paths that are planned but not there yet
+pub fn free_name_with(directory: &Path, name: &Path, taken: impl Fn(&Path) -> bool) -> PathBuf {
+    let mut i = 1;
+    loop {
+        let candidate = directory.join(numbered_name(name, i));
+        if fs::symlink_metadata(&candidate).is_err() && !taken(&candidate) {
+            return candidate;
+        }
+        i += 1;
+    }
+}
+
+/// `name (i).ext`
+pub fn numbered_name(name: &Path, i: usize) -> String {
+    let stem = name
+        .file_stem()
+        .map(|x| x.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = name
+        .extension()
+        .map(|x| format!(".{}", x.to_string_lossy()))
+        .unwrap_or_default();
+    format!("{} ({}){}", stem, i, extension)
+}
+
+pub fn remove_path(path: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(path)?.is_dir() {
+        return fs::remove_dir_all(path);
+    }
+    fs::remove_file(path)
+}
+
+/// copies symlinks as symlinks, and keeps permissions and modification times
+fn copy_path(from: &Path, to: &Path) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(from)?;
+
+    if metadata.file_type().is_symlink() {
+        let target = fs::read_link(from)?;
+        return symlink::symlink_auto(target, to);
+    }
+
+    if metadata.is_dir() {
+        fs::create_dir(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_path(&entry.path(), &to.join(entry.file_name()))?;
+        }
+    } else {
+        // also copies the permissions
+        fs::copy(from, to)?;
+    }
+
+    let mut times = FileTimes::new();
+    if let Ok(modified) = metadata.modified() {
+        times = times.set_modified(modified);
+    }
+    if let Ok(accessed) = metadata.accessed() {
+        times = times.set_accessed(accessed);
+    }
+    if metadata.is_dir() {
+        fs::set_permissions(to, metadata.permissions())?;
+        // directories can only be opened for reading, which is enough to set the times on unix
+        if let Ok(directory) = File::open(to) {
+            let _ = directory.set_times(times);
+        }
+    } else {
+        File::options().write(true).open(to)?.set_times(times)?;
+    }
+    Ok(())
+}
+
+/// checks that the copy has the same files with the same content as the source
+fn verify(from: &Path, to: &Path) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(from)?;
+
+    let same = if metadata.file_type().is_symlink() {
+        fs::read_link(from)? == fs::read_link(to)?
+    } else if metadata.is_dir() {
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            verify(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        true
+    } else {
+        same_content(from, to)?
+    };
+
+    if !same {
+        return Err(io::Error::other(format!(
+            "the copy of {} is not the same as the original",
+            from.display()
+        )));
+    }
+    Ok(())
+}
+
+fn same_content(a: &Path, b: &Path) -> io::Result<bool> {
+    if fs::metadata(a)?.len() != fs::metadata(b)?.len() {
+        return Ok(false);
+    }
+
+    let mut a = BufReader::new(File::open(a)?);
+    let mut b = BufReader::new(File::open(b)?);
+    let mut buffer_a = vec![0; 64 * 1024];
+    let mut buffer_b = vec![0; 64 * 1024];
+    loop {
+        let read = a.read(&mut buffer_a)?;
+        if read == 0 {
+            return Ok(true);
+        }
+        b.read_exact(&mut buffer_b[..read])?;
+        if buffer_a[..read] != buffer_b[..read] {
+            return Ok(false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(unix)]
+    use std::os::unix::fs::PermissionsExt;
+    #[cfg(unix)]
+    use std::time::{Duration, SystemTime};
+
+    /// a directory of its own under the temp dir, anything left from an earlier run is removed first
+    fn directory(test: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("tf-transfer-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn is_symlink(path: &Path) -> bool {
+        fs::symlink_metadata(path).is_ok_and(|x| x.file_type().is_symlink())
+    }
+
+    // permissions are only compared on unix
+    #[cfg(unix)]
+    #[test]
+    fn move_across_keeps_everything() {
+        let directory = directory("across");
+        let from = directory.join("from");
+        let to = directory.join("to");
+        fs::create_dir_all(from.join("sub")).unwrap();
+        fs::write(from.join("sub").join("a.txt"), "a").unwrap();
+        fs::write(from.join("run.sh"), "b").unwrap();
+        fs::set_permissions(from.join("run.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        File::options()
+            .write(true)
+            .open(from.join("run.sh"))
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        symlink::symlink_file("sub/a.txt", from.join("link")).unwrap();
+
+        move_across(&from, &to).unwrap();
+
+        assert!(fs::symlink_metadata(&from).is_err());
+        assert_eq!(
+            fs::read_to_string(to.join("sub").join("a.txt")).unwrap(),
+            "a"
+        );
+        let metadata = fs::metadata(to.join("run.sh")).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o755);
+        assert_eq!(metadata.modified().unwrap(), modified);
+        assert_eq!(
+            fs::read_link(to.join("link")).unwrap(),
+            Path::new("sub/a.txt")
+        );
+    }
+
+    #[test]
+    fn failed_copy_keeps_the_source() {
+        let directory = directory("failed-copy");
+        let from = directory.join("a.txt");
+        fs::write(&from, "a").unwrap();
+        let to = directory.join("missing").join("a.txt");
+
+        assert!(move_across(&from, &to).is_err());
+        assert_eq!(fs::read_to_string(&from).unwrap(), "a");
+        assert!(fs::symlink_metadata(&to).is_err());
+    }
+
+    #[test]
+    fn failed_link_moves_the_file_back() {
+        let directory = directory("failed-link");
+        let from = directory.join("a.txt");
+        let to = directory.join("b.txt");
+        fs::write(&from, "a").unwrap();
+
+        let result = move_and_link(&from, &to, |_, _| Err(io::Error::other("no links here")));
+        assert!(result.is_err());
+        assert!(!is_symlink(&from));
+        assert_eq!(fs::read_to_string(&from).unwrap(), "a");
+        assert!(fs::symlink_metadata(&to).is_err());
+    }
+
+    #[test]
+    fn revert_undoes_transfer() {
+        let directory = directory("revert");
+        for mode in [
+            AddMode::None,
+            AddMode::Move,
+            AddMode::Copy,
+            AddMode::Link,
+            AddMode::Hardlink,
+        ] {
+            let from = directory.join(format!("{}.txt", mode.name()));
+            let to = directory.join(format!("{}-stored.txt", mode.name()));
+            fs::write(&from, "a").unwrap();
+
+            transfer(&from, &to, mode).unwrap();
+            if mode != AddMode::None {
+                assert_eq!(fs::read_to_string(&to).unwrap(), "a");
+            }
+            assert_eq!(is_symlink(&from), mode == AddMode::Link);
+
+            revert(&from, &to, mode).unwrap();
+            assert!(!is_symlink(&from));
+            assert_eq!(fs::read_to_string(&from).unwrap(), "a");
+            assert!(fs::symlink_metadata(&to).is_err());
+        }
+    }
+}
@@ -1,14 +1,180 @@
-use serde::{Deserialize, Serialize};
+use serde::de::{self, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::ffi::{OsStr, OsString};
 use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 // TODO add marker for files outside and inside of the special directory
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 pub struct StoredFile {
+    /// the file name, bytes that are not valid utf-8 are written as `\xff`
     pub name: String,
-    pub path: String,
+    #[serde(
+        serialize_with = "serialize_path",
+        deserialize_with = "deserialize_path"
+    )]
+    pub path: PathBuf,
     pub last_used: u64, // epoch
+    #[serde(default)]
+    pub use_count: u32,
+    /// how the file got into the managed directory
+    #[serde(default)]
+    pub mode: AddMode,
+    /// where the file was before it was added, for every mode except `None`
+    #[serde(
+        default,
+        serialize_with = "serialize_optional_path",
+        deserialize_with = "deserialize_optional_path"
+    )]
+    pub original: Option<PathBuf>,
 }
+
+/// how a file relates to the managed directory
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AddMode {
+    /// the file stays where it is, only its path is stored
+    #[default]
+    None,
+    /// the file was moved into the managed directory
+    Move,
+    /// a copy is in the managed directory, the original stays where it is
+    Copy,
+    /// the file was moved and a symlink to it was left at the original path
+    Link,
+    /// the managed directory has a hard link to the original
+    Hardlink,
+}
+impl AddMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AddMode::None => "none",
+            AddMode::Move => "move",
+            AddMode::Copy => "copy",
+            AddMode::Link => "link",
+            AddMode::Hardlink => "hardlink",
+        }
+    }
+
+    pub fn past_tense(&self) -> &'static str {
+        match self {
+            AddMode::None => "added",
+            AddMode::Move => "moved",
+            AddMode::Copy => "copied",
+            AddMode::Link => "moved and linked",
+            AddMode::Hardlink => "hard linked",
+        }
+    }
+}
+impl StoredFile {
+    /// the name for a file at this path, before `Database` makes sure no other file has it
+    pub fn name_of(path: &Path) -> Option<String> {
+        path.file_name().map(escape_os_str)
+    }
+
+    /// how often the file is used, weighted by how recent the last use was, like zoxide does it
+    pub fn frecency(&self, now: u64) -> f64 {
+        let age = now.saturating_sub(self.last_used);
+        let count = self.use_count as f64;
+        if age < 60 * 60 {
+            count * 4.0
+        } else if age < 60 * 60 * 24 {
+            count * 2.0
+        } else if age < 60 * 60 * 24 * 7 {
+            count / 2.0
+        } else {
+            count / 4.0
+        }
+    }
+}
+/// the text, bytes that are not valid utf-8 are written as `\xff` so different names stay different
+pub fn escape_os_str(text: &OsStr) -> String {
+    let mut result = String::new();
+    for chunk in text.as_encoded_bytes().utf8_chunks() {
+        result.push_str(chunk.valid());
+        for byte in chunk.invalid() {
+            result.push_str(&format!("\\x{:02x}", byte));
+        }
+    }
+    result
+}
+
+/// paths are written as a string when they are valid utf-8, otherwise as a list of their bytes so nothing is lost
+pub fn serialize_path<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+    match path.to_str() {
+        Some(text) => serializer.serialize_str(text),
+        None => serializer.collect_seq(path.as_os_str().as_encoded_bytes()),
+    }
+}
+
+pub fn serialize_optional_path<S: Serializer>(
+    path: &Option<PathBuf>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match path {
+        Some(path) => serializer.serialize_some(&SerializePath(path)),
+        None => serializer.serialize_none(),
+    }
+}
+
+pub fn deserialize_optional_path<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<PathBuf>, D::Error> {
+    // transparent, otherwise ron expects the wrapper in parentheses
+    #[derive(Deserialize)]
+    #[serde(transparent)]
+    struct Wrapper(#[serde(deserialize_with = "deserialize_path")] PathBuf);
+
+    let wrapper: Option<Wrapper> = Option::deserialize(deserializer)?;
+    Ok(wrapper.map(|x| x.0))
+}
+
+struct SerializePath<'a>(&'a Path);
+impl Serialize for SerializePath<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_path(self.0, serializer)
+    }
+}
+
+pub fn deserialize_path<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PathBuf, D::Error> {
+    deserializer.deserialize_any(PathVisitor)
+}
+
+struct PathVisitor;
+impl<'de> Visitor<'de> for PathVisitor {
+    type Value = PathBuf;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "a path as a string or a list of bytes")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<PathBuf, E> {
+        Ok(PathBuf::from(v))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<PathBuf, A::Error> {
+        let mut bytes: Vec<u8> = vec![];
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+        Ok(PathBuf::from(os_string_from_bytes(bytes)))
+    }
+}
+
+#[cfg(unix)]
+pub fn os_string_from_bytes(bytes: Vec<u8>) -> OsString {
+    use std::os::unix::ffi::OsStringExt;
+    OsString::from_vec(bytes)
+}
+
+// other platforms cant have paths that are not valid utf-8 in the first place
+#[cfg(not(unix))]
+pub fn os_string_from_bytes(bytes: Vec<u8>) -> OsString {
+    OsString::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
 impl Display for StoredFile {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.name)
@@ -16,19 +182,104 @@ impl Display for StoredFile {
 }
 
 // TODO add marker for files special and autodetect tags
-// also maybe colors :)
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 pub struct Tag {
     pub name: String,
+    #[serde(default)]
     pub children: Vec<ChildTag>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub color: Option<TagColor>,
+    /// an icon or emoji shown in front of the tag name
+    #[serde(default)]
+    pub icon: Option<String>,
+}
+impl Tag {
+    pub fn new(name: String) -> Tag {
+        Tag {
+            name,
+            children: vec![],
+            description: None,
+            color: None,
+            icon: None,
+        }
+    }
+}
+
+/// the basic ansi colours, these should look alright in every terminal theme
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TagColor {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+}
+impl TagColor {
+    pub const ALL: [TagColor; 8] = [
+        TagColor::Black,
+        TagColor::Red,
+        TagColor::Green,
+        TagColor::Yellow,
+        TagColor::Blue,
+        TagColor::Magenta,
+        TagColor::Cyan,
+        TagColor::White,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TagColor::Black => "black",
+            TagColor::Red => "red",
+            TagColor::Green => "green",
+            TagColor::Yellow => "yellow",
+            TagColor::Blue => "blue",
+            TagColor::Magenta => "magenta",
+            TagColor::Cyan => "cyan",
+            TagColor::White => "white",
+        }
+    }
+
+    /// the ansi foreground colour code
+    pub fn ansi_code(&self) -> u8 {
+        match self {
+            TagColor::Black => 30,
+            TagColor::Red => 31,
+            TagColor::Green => 32,
+            TagColor::Yellow => 33,
+            TagColor::Blue => 34,
+            TagColor::Magenta => 35,
+            TagColor::Cyan => 36,
+            TagColor::White => 37,
+        }
+    }
+}
+impl FromStr for TagColor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        TagColor::ALL
+            .into_iter()
+            .find(|x| x.name() == s.to_lowercase())
+            .ok_or(format!(
+                "unknown colour '{}', expected one of: {}",
+                s,
+                TagColor::ALL.map(|x| x.name()).join(", ")
+            ))
+    }
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 pub struct ChildTag {
     pub name: String,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 pub struct FileTagConnection {
     pub file_name: String,
     pub tag_name: String,
@@ -44,9 +295,9 @@ pub struct TagFilter {
 
 impl TagFilter {
     pub fn is_empty(&self) -> bool {
-        return self.allowed_tags.is_empty()
+        self.allowed_tags.is_empty()
             && self.denied_tags.is_empty()
             && self.allowed_child_tags.is_empty()
-            && self.denied_child_tags.is_empty();
+            && self.denied_child_tags.is_empty()
     }
 }
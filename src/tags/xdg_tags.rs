@@ -0,0 +1,64 @@
+use std::io;
+use std::path::Path;
+
+use super::tag_relations::validate_tag_name;
+
+/// the extended attribute from the freedesktop spec, dolphin, baloo and `getfattr` read it
+pub const ATTRIBUTE: &str = "user.xdg.tags";
+
+/// the tags in the attribute, `None` when the file does not have it
+///
+/// fails with `ErrorKind::Unsupported` on filesystems without extended attributes
+pub fn read(path: &Path) -> io::Result<Option<Vec<String>>> {
+    let value = match xattr::get_deref(path, ATTRIBUTE)? {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+    let tags = String::from_utf8_lossy(&value)
+        .split(',')
+        .map(|x| x.trim().to_owned())
+        .filter(|x| !x.is_empty())
+        .collect();
+    Ok(Some(tags))
+}
+
+/// replaces the tags in the attribute, and removes it when there are none
+///
+/// tags other programs wrote that can not be tag names here, like ones with spaces, are kept
+pub fn write(path: &Path, tags: &[String]) -> io::Result<()> {
+    let mut value = split(read(path)?.unwrap_or_default()).1;
+    for tag in tags {
+        if !value.contains(tag) {
+            value.push(tag.clone());
+        }
+    }
+    set(path, value)
+}
+
+/// adds the tags a file got and removes the ones it lost, so tags other programs wrote are left alone
+pub fn update(path: &Path, before: &[String], after: &[String]) -> io::Result<()> {
+    let mut value = read(path)?.unwrap_or_default();
+    value.retain(|x| after.contains(x) || !before.contains(x));
+    for tag in after {
+        if !value.contains(tag) {
+            value.push(tag.clone());
+        }
+    }
+    set(path, value)
+}
+
+fn set(path: &Path, value: Vec<String>) -> io::Result<()> {
+    if value.is_empty() {
+        return match xattr::remove_deref(path, ATTRIBUTE) {
+            // there was nothing to remove
+            Err(_) if read(path)?.is_none() => Ok(()),
+            result => result,
+        };
+    }
+    xattr::set_deref(path, ATTRIBUTE, value.join(",").as_bytes())
+}
+
+/// splits the tags from the attribute into the ones that are valid tag names and the ones that are not
+pub fn split(tags: Vec<String>) -> (Vec<String>, Vec<String>) {
+    tags.into_iter().partition(|x| validate_tag_name(x).is_ok())
+}